@@ -0,0 +1,150 @@
+use num_derive::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+// Errors returned by the token program as `ProgramError::Custom`. The codes are
+// part of the program's interface, new errors only ever go at the end.
+#[derive(Clone, Copy, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum TokenError {
+    #[error("Buy or sell amount exceeds the maximum transaction amount")]
+    MaxTransactionAmountExceeded = 0,
+    #[error("Buy leaves the destination above the maximum wallet balance")]
+    MaxWalletBalanceExceeded = 1,
+    #[error("Trading has not been enabled for the mint")]
+    TradingNotEnabled = 2,
+    #[error("Trading is already enabled for the mint")]
+    TradingAlreadyEnabled = 3,
+    #[error("Wallet sold too recently")]
+    SellCooldownActive = 4,
+    #[error("Sell pushes the wallet over its daily sell volume")]
+    DailySellVolumeExceeded = 5,
+    #[error("Sell record is still tracking recent sells")]
+    SellRecordActive = 6,
+    #[error("Sells into a pool must go through the token program while sell limits are set")]
+    UncheckedSell = 7,
+    #[error("Account is on the mint's blocklist")]
+    AccountBlocked = 8,
+    #[error("Mint would take the supply past the max supply")]
+    MaxSupplyExceeded = 9,
+    #[error("Only native Token-2022 mints carry token metadata")]
+    MetadataNotSupported = 10,
+    #[error("Fee config could not be read")]
+    InvalidFeeConfig = 11,
+    #[error("Rewards program does not match the fee config")]
+    InvalidRewardsProgram = 12,
+    #[error("Fee or penalty exceeds its maximum rate")]
+    FeeTooHigh = 13,
+    #[error("Fee destinations must be 1 to 5 with weights adding up to 10000")]
+    InvalidFeeDestinations = 14,
+    #[error("Account is already on the list")]
+    AccountAlreadyListed = 15,
+    #[error("Account list is full")]
+    AccountListFull = 16,
+    #[error("Account is not on the list")]
+    AccountNotListed = 17,
+    #[error("No authority hand over is pending")]
+    NoPendingAuthority = 18,
+    #[error("Sells must use Transfer")]
+    SellNotAllowed = 19,
+    #[error("Authority is not the delegate of the source account")]
+    NotDelegate = 20,
+    #[error("Delegate allowance does not cover the amount")]
+    InsufficientAllowance = 21,
+    #[error("Permit deadline has passed")]
+    PermitExpired = 22,
+    #[error("Permit nonce is not the owner's next nonce")]
+    InvalidPermitNonce = 23,
+    #[error("Invalid vesting schedule")]
+    InvalidVestingSchedule = 24,
+    #[error("Nothing has vested since the last release")]
+    NothingToRelease = 25,
+    #[error("Vesting is not revocable")]
+    VestingNotRevocable = 26,
+    #[error("Stake is still locked")]
+    StakeLocked = 27,
+    #[error("Stake is already withdrawn")]
+    StakeWithdrawn = 28,
+}
+
+impl From<TokenError> for ProgramError {
+    fn from(e: TokenError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for TokenError {
+    fn type_of() -> &'static str {
+        "TokenError"
+    }
+}
+
+impl PrintProgramError for TokenError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + num_traits::FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
+
+// Errors returned by the rewards program as `ProgramError::Custom`, with codes as
+// stable as those of `TokenError`
+#[derive(Clone, Copy, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum RewardsError {
+    #[error("Distribution interval has not elapsed")]
+    DistributionTooEarly = 0,
+    #[error("Liquidity interval has not elapsed")]
+    LiquidityTooEarly = 1,
+    #[error("Rewards pool could not be read")]
+    InvalidPoolData = 2,
+    #[error("Balance does not match the holder token account")]
+    BalanceMismatch = 3,
+    #[error("No admin hand over is pending")]
+    NoPendingAdmin = 4,
+    #[error("Lock authority does not match the rewards pool")]
+    InvalidLockAuthority = 5,
+}
+
+impl From<RewardsError> for ProgramError {
+    fn from(e: RewardsError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for RewardsError {
+    fn type_of() -> &'static str {
+        "RewardsError"
+    }
+}
+
+impl PrintProgramError for RewardsError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + num_traits::FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_error_codes() {
+        // Clients match on these codes, they must never change
+        assert_eq!(ProgramError::from(TokenError::AccountBlocked), ProgramError::Custom(8));
+        assert_eq!(ProgramError::from(TokenError::StakeWithdrawn), ProgramError::Custom(28));
+        assert_eq!(
+            ProgramError::from(RewardsError::DistributionTooEarly),
+            ProgramError::Custom(0)
+        );
+        assert_eq!(TokenError::from_u32(22), Some(TokenError::PermitExpired));
+        assert_eq!(RewardsError::from_u32(6), None);
+    }
+}
@@ -0,0 +1,290 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+use crate::{unpack_i64, unpack_pubkey, unpack_u16, unpack_u64, TransferDirection};
+
+// Written before every event so other programs' data logs aren't mistaken for
+// events: the first 8 bytes of sha256("spl-2022-token:event")
+pub const EVENT_DISCRIMINATOR: [u8; 8] = [220, 29, 170, 9, 45, 14, 171, 190];
+
+// Layout version written after the discriminator, bumped whenever a layout changes
+pub const EVENT_VERSION: u8 = 1;
+
+// Prefix of the log lines written by `sol_log_data`
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+// A transfer of the token program, with what it was charged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferWithFee {
+    pub mint: Pubkey,
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    pub direction: TransferDirection,
+    pub amount: u64,        // Taken from the source
+    pub fee_amount: u64,    // Part of the amount the destination didn't receive
+    pub burned_amount: u64, // Part of the fee that was burned
+}
+
+// New fee rates, taking effect at `epoch`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeConfigUpdated {
+    pub mint: Pubkey,
+    pub epoch: u64,
+    pub buy_fee_basis_points: u16,
+    pub sell_fee_basis_points: u16,
+    pub transfer_fee_basis_points: u16,
+}
+
+// A rewards distribution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardsDistributed {
+    pub pool: Pubkey,
+    pub reserve_amount: u64,  // WBTC sent to the reserve wallet
+    pub holders_amount: u64,  // WBTC credited to the holders, 0 with none recorded
+    pub eligible_supply: u64, // Balance the holders amount is shared across
+    pub timestamp: i64,
+}
+
+// WBTC claimed for a holder token account, paid to its owner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HolderPaid {
+    pub pool: Pubkey,
+    pub holder: Pubkey, // Holder token account
+    pub owner: Pubkey,  // Wallet whose WBTC account was paid
+    pub amount: u64,
+}
+
+// Liquidity added from the reserve wallet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiquidityAdded {
+    pub pool: Pubkey,
+    pub reserve_wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    TransferWithFee(TransferWithFee),
+    FeeConfigUpdated(FeeConfigUpdated),
+    RewardsDistributed(RewardsDistributed),
+    HolderPaid(HolderPaid),
+    LiquidityAdded(LiquidityAdded),
+}
+
+impl Event {
+    // The discriminator, the version, the kind of event, then its fields in order,
+    // integers little endian
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = EVENT_DISCRIMINATOR.to_vec();
+        data.push(EVENT_VERSION);
+        match self {
+            Self::TransferWithFee(event) => {
+                data.push(0);
+                data.extend_from_slice(event.mint.as_ref());
+                data.extend_from_slice(event.source.as_ref());
+                data.extend_from_slice(event.destination.as_ref());
+                data.push(match event.direction {
+                    TransferDirection::Buy => 0,
+                    TransferDirection::Sell => 1,
+                    TransferDirection::Transfer => 2,
+                });
+                data.extend_from_slice(&event.amount.to_le_bytes());
+                data.extend_from_slice(&event.fee_amount.to_le_bytes());
+                data.extend_from_slice(&event.burned_amount.to_le_bytes());
+            }
+            Self::FeeConfigUpdated(event) => {
+                data.push(1);
+                data.extend_from_slice(event.mint.as_ref());
+                data.extend_from_slice(&event.epoch.to_le_bytes());
+                data.extend_from_slice(&event.buy_fee_basis_points.to_le_bytes());
+                data.extend_from_slice(&event.sell_fee_basis_points.to_le_bytes());
+                data.extend_from_slice(&event.transfer_fee_basis_points.to_le_bytes());
+            }
+            Self::RewardsDistributed(event) => {
+                data.push(2);
+                data.extend_from_slice(event.pool.as_ref());
+                data.extend_from_slice(&event.reserve_amount.to_le_bytes());
+                data.extend_from_slice(&event.holders_amount.to_le_bytes());
+                data.extend_from_slice(&event.eligible_supply.to_le_bytes());
+                data.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+            Self::HolderPaid(event) => {
+                data.push(3);
+                data.extend_from_slice(event.pool.as_ref());
+                data.extend_from_slice(event.holder.as_ref());
+                data.extend_from_slice(event.owner.as_ref());
+                data.extend_from_slice(&event.amount.to_le_bytes());
+            }
+            Self::LiquidityAdded(event) => {
+                data.push(4);
+                data.extend_from_slice(event.pool.as_ref());
+                data.extend_from_slice(event.reserve_wallet.as_ref());
+                data.extend_from_slice(&event.timestamp.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    // None for data of another version or that isn't an event
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let rest = data.strip_prefix(&EVENT_DISCRIMINATOR[..])?;
+        let (&version, rest) = rest.split_first()?;
+        if version != EVENT_VERSION {
+            return None;
+        }
+        let (&kind, rest) = rest.split_first()?;
+        let (event, rest) = match kind {
+            0 => {
+                let (mint, rest) = unpack_pubkey(rest).ok()?;
+                let (source, rest) = unpack_pubkey(rest).ok()?;
+                let (destination, rest) = unpack_pubkey(rest).ok()?;
+                let (&direction, rest) = rest.split_first()?;
+                let direction = match direction {
+                    0 => TransferDirection::Buy,
+                    1 => TransferDirection::Sell,
+                    2 => TransferDirection::Transfer,
+                    _ => return None,
+                };
+                let (amount, rest) = unpack_u64(rest).ok()?;
+                let (fee_amount, rest) = unpack_u64(rest).ok()?;
+                let (burned_amount, rest) = unpack_u64(rest).ok()?;
+                let event = TransferWithFee {
+                    mint,
+                    source,
+                    destination,
+                    direction,
+                    amount,
+                    fee_amount,
+                    burned_amount,
+                };
+                (Self::TransferWithFee(event), rest)
+            }
+            1 => {
+                let (mint, rest) = unpack_pubkey(rest).ok()?;
+                let (epoch, rest) = unpack_u64(rest).ok()?;
+                let (buy_fee_basis_points, rest) = unpack_u16(rest).ok()?;
+                let (sell_fee_basis_points, rest) = unpack_u16(rest).ok()?;
+                let (transfer_fee_basis_points, rest) = unpack_u16(rest).ok()?;
+                let event = FeeConfigUpdated {
+                    mint,
+                    epoch,
+                    buy_fee_basis_points,
+                    sell_fee_basis_points,
+                    transfer_fee_basis_points,
+                };
+                (Self::FeeConfigUpdated(event), rest)
+            }
+            2 => {
+                let (pool, rest) = unpack_pubkey(rest).ok()?;
+                let (reserve_amount, rest) = unpack_u64(rest).ok()?;
+                let (holders_amount, rest) = unpack_u64(rest).ok()?;
+                let (eligible_supply, rest) = unpack_u64(rest).ok()?;
+                let (timestamp, rest) = unpack_i64(rest).ok()?;
+                let event = RewardsDistributed {
+                    pool,
+                    reserve_amount,
+                    holders_amount,
+                    eligible_supply,
+                    timestamp,
+                };
+                (Self::RewardsDistributed(event), rest)
+            }
+            3 => {
+                let (pool, rest) = unpack_pubkey(rest).ok()?;
+                let (holder, rest) = unpack_pubkey(rest).ok()?;
+                let (owner, rest) = unpack_pubkey(rest).ok()?;
+                let (amount, rest) = unpack_u64(rest).ok()?;
+                let event = HolderPaid {
+                    pool,
+                    holder,
+                    owner,
+                    amount,
+                };
+                (Self::HolderPaid(event), rest)
+            }
+            4 => {
+                let (pool, rest) = unpack_pubkey(rest).ok()?;
+                let (reserve_wallet, rest) = unpack_pubkey(rest).ok()?;
+                let (timestamp, rest) = unpack_i64(rest).ok()?;
+                let event = LiquidityAdded {
+                    pool,
+                    reserve_wallet,
+                    timestamp,
+                };
+                (Self::LiquidityAdded(event), rest)
+            }
+            _ => return None,
+        };
+        rest.is_empty().then_some(event)
+    }
+
+    // Write the event to the transaction logs as a single data field
+    pub fn emit(&self) {
+        sol_log_data(&[&self.pack()]);
+    }
+}
+
+// Event of a "Program data: " log line, None for any other line
+pub fn decode_log(log: &str) -> Option<Event> {
+    let data = log.strip_prefix(PROGRAM_DATA_PREFIX)?;
+    if data.contains(' ') {
+        return None;
+    }
+    Event::unpack(&STANDARD.decode(data).ok()?)
+}
+
+// Events in the log messages of a transaction, in order. Other programs may log
+// data too, so callers should only pass the logs of this program's invocations.
+pub fn decode_logs<S: AsRef<str>>(logs: &[S]) -> Vec<Event> {
+    logs.iter().filter_map(|log| decode_log(log.as_ref())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_logs() {
+        let transfer = Event::TransferWithFee(TransferWithFee {
+            mint: Pubkey::new_unique(),
+            source: Pubkey::new_unique(),
+            destination: Pubkey::new_unique(),
+            direction: TransferDirection::Sell,
+            amount: 1_000,
+            fee_amount: 80,
+            burned_amount: 10,
+        });
+        let distribution = Event::RewardsDistributed(RewardsDistributed {
+            pool: Pubkey::new_unique(),
+            reserve_amount: 500,
+            holders_amount: 499,
+            eligible_supply: 1_000_000,
+            timestamp: 1_700_000_000,
+        });
+        let logs = vec![
+            "Program log: Instruction: Transfer".to_string(),
+            format!("{}{}", PROGRAM_DATA_PREFIX, STANDARD.encode(transfer.pack())),
+            format!("{}{}", PROGRAM_DATA_PREFIX, STANDARD.encode(distribution.pack())),
+        ];
+        assert_eq!(decode_logs(&logs), vec![transfer, distribution]);
+
+        // Data of another program or version isn't an event
+        let mut data = transfer.pack();
+        data[0] ^= 1;
+        assert_eq!(Event::unpack(&data), None);
+        let mut data = transfer.pack();
+        data[EVENT_DISCRIMINATOR.len()] = EVENT_VERSION + 1;
+        assert_eq!(Event::unpack(&data), None);
+
+        // Truncated or padded data isn't an event
+        let data = transfer.pack();
+        assert_eq!(Event::unpack(&data[..data.len() - 1]), None);
+        assert_eq!(Event::unpack(&[&data[..], &[0]].concat()), None);
+    }
+
+    #[test]
+    fn test_event_discriminator() {
+        let hash = solana_program::hash::hash(b"spl-2022-token:event");
+        assert_eq!(EVENT_DISCRIMINATOR, hash.to_bytes()[..8]);
+    }
+}
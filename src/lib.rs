@@ -1,48 +1,374 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
     msg,
-    program_error::ProgramError,
+    program_error::{PrintProgramError, ProgramError},
+    program_option::COption,
+    program_pack::Pack,
     pubkey::Pubkey,
-    program::invoke,
+    program::{invoke, invoke_signed},
     system_instruction,
+    system_program,
+    clock::Clock,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+use serde::{Deserialize, Serialize};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction as associated_token_instruction,
 };
 use spl_token_2022::{
+    extension::{
+        metadata_pointer::instruction as metadata_pointer_instruction,
+        transfer_fee::{
+            instruction as transfer_fee_instruction,
+            TransferFeeConfig as NativeTransferFeeConfig,
+        },
+        transfer_hook::{
+            self as transfer_hook_extension,
+            instruction as transfer_hook_instruction,
+        },
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
     instruction as token_instruction,
     state::{Account, Mint},
 };
+use spl_token_metadata_interface::{
+    instruction as metadata_instruction,
+    state::{Field, TokenMetadata},
+};
+use spl_transfer_hook_interface::onchain::add_cpi_accounts_for_execute;
 
+pub mod error;
+pub mod events;
+mod multisig;
+mod permit;
 mod rewards;
+mod staking;
+mod transfer_hook;
+mod vesting;
+
+use error::TokenError;
 
-// Declare the program's entrypoint
-entrypoint!(process_instruction);
+#[cfg(all(feature = "rewards-program", feature = "transfer-hook-program"))]
+compile_error!("enable at most one of the rewards-program and transfer-hook-program features");
 
-// Program entrypoint implementation
+// Declare the program's entrypoint, unless building one of the other programs
+#[cfg(not(any(feature = "rewards-program", feature = "transfer-hook-program")))]
+solana_program::entrypoint!(process_instruction);
+
+// Program entrypoint implementation, logging the message of the error it fails with
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
+) -> ProgramResult {
+    if let Err(error) = process_token_instruction(program_id, accounts, instruction_data) {
+        error.print::<TokenError>();
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn process_token_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let instruction = TokenInstruction::unpack(instruction_data)?;
 
     match instruction {
-        TokenInstruction::InitializeMint { decimals, mint_authority } => {
+        TokenInstruction::InitializeMint {
+            decimals,
+            mint_authority,
+            buy_fee_basis_points,
+            sell_fee_basis_points,
+            transfer_fee_basis_points,
+            freeze_authority_enabled,
+            max_supply,
+        } => {
             msg!("Instruction: InitializeMint");
-            process_initialize_mint(program_id, accounts, decimals, mint_authority)
+            process_initialize_mint(
+                program_id,
+                accounts,
+                decimals,
+                mint_authority,
+                buy_fee_basis_points,
+                sell_fee_basis_points,
+                transfer_fee_basis_points,
+                freeze_authority_enabled,
+                max_supply,
+            )
         }
         TokenInstruction::MintTo { amount } => {
             msg!("Instruction: MintTo");
             process_mint_to(program_id, accounts, amount)
         }
-        TokenInstruction::Transfer { amount, is_buy } => {
+        TokenInstruction::Transfer {
+            amount,
+            idle_sell_records,
+            create_destination,
+        } => {
             msg!("Instruction: Transfer");
-            process_transfer(program_id, accounts, amount, is_buy)
+            process_transfer(
+                program_id,
+                accounts,
+                amount,
+                idle_sell_records,
+                create_destination,
+            )
         }
         TokenInstruction::UpdateHolderBalance { holder, balance } => {
             msg!("Instruction: UpdateHolderBalance");
             process_update_holder_balance(program_id, accounts, holder, balance)
         }
+        TokenInstruction::SetTransferFee {
+            buy_fee_basis_points,
+            sell_fee_basis_points,
+            transfer_fee_basis_points,
+        } => {
+            msg!("Instruction: SetTransferFee");
+            process_set_transfer_fee(
+                program_id,
+                accounts,
+                buy_fee_basis_points,
+                sell_fee_basis_points,
+                transfer_fee_basis_points,
+            )
+        }
+        TokenInstruction::InitializeNativeFeeMint {
+            decimals,
+            mint_authority,
+            buy_fee_basis_points,
+            sell_fee_basis_points,
+            transfer_fee_basis_points,
+            maximum_fee,
+            freeze_authority_enabled,
+            max_supply,
+            metadata,
+        } => {
+            msg!("Instruction: InitializeNativeFeeMint");
+            process_initialize_native_fee_mint(
+                program_id,
+                accounts,
+                decimals,
+                mint_authority,
+                buy_fee_basis_points,
+                sell_fee_basis_points,
+                transfer_fee_basis_points,
+                maximum_fee,
+                freeze_authority_enabled,
+                max_supply,
+                metadata,
+            )
+        }
+        TokenInstruction::HarvestWithheldFees => {
+            msg!("Instruction: HarvestWithheldFees");
+            process_harvest_withheld_fees(program_id, accounts)
+        }
+        TokenInstruction::AddPool { pool } => {
+            msg!("Instruction: AddPool");
+            process_add_pool(program_id, accounts, pool)
+        }
+        TokenInstruction::RemovePool { pool } => {
+            msg!("Instruction: RemovePool");
+            process_remove_pool(program_id, accounts, pool)
+        }
+        TokenInstruction::AddExemptAccount { account } => {
+            msg!("Instruction: AddExemptAccount");
+            process_add_exempt_account(program_id, accounts, account)
+        }
+        TokenInstruction::RemoveExemptAccount { account } => {
+            msg!("Instruction: RemoveExemptAccount");
+            process_remove_exempt_account(program_id, accounts, account)
+        }
+        TokenInstruction::SetFeeDestinations { fee_destinations } => {
+            msg!("Instruction: SetFeeDestinations");
+            process_set_fee_destinations(program_id, accounts, fee_destinations)
+        }
+        TokenInstruction::SetTransferLimits {
+            max_transaction_amount,
+            max_wallet_balance,
+        } => {
+            msg!("Instruction: SetTransferLimits");
+            process_set_transfer_limits(
+                program_id,
+                accounts,
+                max_transaction_amount,
+                max_wallet_balance,
+            )
+        }
+        TokenInstruction::EnableTrading {
+            launch_window_slots,
+            launch_buy_fee_basis_points,
+            launch_sell_fee_basis_points,
+        } => {
+            msg!("Instruction: EnableTrading");
+            process_enable_trading(
+                program_id,
+                accounts,
+                launch_window_slots,
+                launch_buy_fee_basis_points,
+                launch_sell_fee_basis_points,
+            )
+        }
+        TokenInstruction::SetSellLimits {
+            sell_cooldown_seconds,
+            max_daily_sell_volume,
+        } => {
+            msg!("Instruction: SetSellLimits");
+            process_set_sell_limits(
+                program_id,
+                accounts,
+                sell_cooldown_seconds,
+                max_daily_sell_volume,
+            )
+        }
+        TokenInstruction::SetComplianceAuthority { compliance_authority } => {
+            msg!("Instruction: SetComplianceAuthority");
+            process_set_compliance_authority(program_id, accounts, compliance_authority)
+        }
+        TokenInstruction::FreezeAccount => {
+            msg!("Instruction: FreezeAccount");
+            process_freeze_account(program_id, accounts, true)
+        }
+        TokenInstruction::ThawAccount => {
+            msg!("Instruction: ThawAccount");
+            process_freeze_account(program_id, accounts, false)
+        }
+        TokenInstruction::AddBlockedAccount { account } => {
+            msg!("Instruction: AddBlockedAccount");
+            process_add_blocked_account(program_id, accounts, account)
+        }
+        TokenInstruction::RemoveBlockedAccount { account } => {
+            msg!("Instruction: RemoveBlockedAccount");
+            process_remove_blocked_account(program_id, accounts, account)
+        }
+        TokenInstruction::ProposeAuthority {
+            authority_type,
+            new_authority,
+        } => {
+            msg!("Instruction: ProposeAuthority");
+            process_propose_authority(program_id, accounts, authority_type, new_authority)
+        }
+        TokenInstruction::AcceptAuthority { authority_type } => {
+            msg!("Instruction: AcceptAuthority");
+            process_accept_authority(program_id, accounts, authority_type)
+        }
+        TokenInstruction::InitializeMultisig { threshold } => {
+            msg!("Instruction: InitializeMultisig");
+            multisig::process_initialize_multisig(program_id, accounts, threshold)
+        }
+        TokenInstruction::FinalizeSupply => {
+            msg!("Instruction: FinalizeSupply");
+            process_finalize_supply(program_id, accounts)
+        }
+        TokenInstruction::Burn { amount } => {
+            msg!("Instruction: Burn");
+            process_burn(program_id, accounts, amount)
+        }
+        TokenInstruction::UpdateMetadata { field, value } => {
+            msg!("Instruction: UpdateMetadata");
+            process_update_metadata(program_id, accounts, field, value)
+        }
+        TokenInstruction::SetMetadataUpdateAuthority {
+            metadata_update_authority,
+        } => {
+            msg!("Instruction: SetMetadataUpdateAuthority");
+            process_set_metadata_update_authority(program_id, accounts, metadata_update_authority)
+        }
+        TokenInstruction::BatchTransfer { transfers } => {
+            msg!("Instruction: BatchTransfer");
+            process_batch_transfer(program_id, accounts, &transfers)
+        }
+        TokenInstruction::CreateVesting {
+            start_timestamp,
+            cliff_seconds,
+            duration_seconds,
+            amount,
+            revocable,
+        } => {
+            msg!("Instruction: CreateVesting");
+            vesting::process_create_vesting(
+                program_id,
+                accounts,
+                start_timestamp,
+                cliff_seconds,
+                duration_seconds,
+                amount,
+                revocable,
+            )
+        }
+        TokenInstruction::ReleaseVested => {
+            msg!("Instruction: ReleaseVested");
+            vesting::process_release_vested(program_id, accounts)
+        }
+        TokenInstruction::RevokeVesting => {
+            msg!("Instruction: RevokeVesting");
+            vesting::process_revoke_vesting(program_id, accounts)
+        }
+        TokenInstruction::SetVestingRewardsEligibility { enabled } => {
+            msg!("Instruction: SetVestingRewardsEligibility");
+            process_set_vesting_rewards_eligibility(program_id, accounts, enabled)
+        }
+        TokenInstruction::Stake {
+            amount,
+            lock_seconds,
+        } => {
+            msg!("Instruction: Stake");
+            staking::process_stake(program_id, accounts, amount, lock_seconds)
+        }
+        TokenInstruction::Unstake => {
+            msg!("Instruction: Unstake");
+            staking::process_unstake(program_id, accounts)
+        }
+        TokenInstruction::SetEarlyUnstakePenalty {
+            penalty_basis_points,
+        } => {
+            msg!("Instruction: SetEarlyUnstakePenalty");
+            process_set_early_unstake_penalty(program_id, accounts, penalty_basis_points)
+        }
+        TokenInstruction::PermitTransfer {
+            amount,
+            nonce,
+            deadline,
+            idle_sell_records,
+            create_destination,
+        } => {
+            msg!("Instruction: PermitTransfer");
+            permit::process_permit_transfer(
+                program_id,
+                accounts,
+                amount,
+                nonce,
+                deadline,
+                idle_sell_records,
+                create_destination,
+            )
+        }
+        TokenInstruction::Approve { amount } => {
+            msg!("Instruction: Approve");
+            process_approve(program_id, accounts, amount)
+        }
+        TokenInstruction::Revoke => {
+            msg!("Instruction: Revoke");
+            process_revoke(accounts)
+        }
+        TokenInstruction::TransferFrom {
+            amount,
+            idle_sell_records,
+            create_destination,
+        } => {
+            msg!("Instruction: TransferFrom");
+            process_transfer_from(
+                program_id,
+                accounts,
+                amount,
+                idle_sell_records,
+                create_destination,
+            )
+        }
     }
 }
 
@@ -51,18 +377,193 @@ enum TokenInstruction {
     InitializeMint {
         decimals: u8,
         mint_authority: Option<Pubkey>,
+        buy_fee_basis_points: u16,
+        sell_fee_basis_points: u16,
+        transfer_fee_basis_points: u16,
+        freeze_authority_enabled: bool,
+        max_supply: Option<u64>,
     },
     MintTo {
         amount: u64,
     },
     Transfer {
         amount: u64,
-        is_buy: bool,
+        idle_sell_records: u8, // Idle sell records to close along with a sell
+        create_destination: bool, // Create the destination ATA if it doesn't exist
     },
     UpdateHolderBalance {
         holder: Pubkey,
         balance: u64,
     },
+    SetTransferFee {
+        buy_fee_basis_points: u16,
+        sell_fee_basis_points: u16,
+        transfer_fee_basis_points: u16,
+    },
+    InitializeNativeFeeMint {
+        decimals: u8,
+        mint_authority: Pubkey,
+        buy_fee_basis_points: u16,
+        sell_fee_basis_points: u16,
+        transfer_fee_basis_points: u16,
+        maximum_fee: u64,
+        freeze_authority_enabled: bool,
+        max_supply: Option<u64>,
+        metadata: Option<MintMetadata>,
+    },
+    HarvestWithheldFees,
+    AddPool {
+        pool: Pubkey,
+    },
+    RemovePool {
+        pool: Pubkey,
+    },
+    AddExemptAccount {
+        account: Pubkey,
+    },
+    RemoveExemptAccount {
+        account: Pubkey,
+    },
+    SetFeeDestinations {
+        fee_destinations: Vec<FeeDestination>,
+    },
+    SetTransferLimits {
+        max_transaction_amount: Option<u64>,
+        max_wallet_balance: Option<u64>,
+    },
+    EnableTrading {
+        launch_window_slots: u64,
+        launch_buy_fee_basis_points: u16,
+        launch_sell_fee_basis_points: u16,
+    },
+    SetSellLimits {
+        sell_cooldown_seconds: Option<u64>,
+        max_daily_sell_volume: Option<u64>,
+    },
+    SetComplianceAuthority {
+        compliance_authority: Option<Pubkey>,
+    },
+    FreezeAccount,
+    ThawAccount,
+    AddBlockedAccount {
+        account: Pubkey,
+    },
+    RemoveBlockedAccount {
+        account: Pubkey,
+    },
+    ProposeAuthority {
+        authority_type: AuthorityType,
+        new_authority: Option<Pubkey>, // None cancels the pending proposal
+    },
+    AcceptAuthority {
+        authority_type: AuthorityType,
+    },
+    InitializeMultisig {
+        threshold: u8,
+    },
+    FinalizeSupply,
+    Burn {
+        amount: u64,
+    },
+    UpdateMetadata {
+        field: Field,
+        value: String,
+    },
+    SetMetadataUpdateAuthority {
+        metadata_update_authority: Option<Pubkey>, // None makes the metadata immutable
+    },
+    BatchTransfer {
+        transfers: Vec<(Pubkey, u64)>, // Destination token account and amount
+    },
+    CreateVesting {
+        start_timestamp: i64,
+        cliff_seconds: u64, // Counted from the start, nothing is released before
+        duration_seconds: u64, // Counted from the start, everything is vested after
+        amount: u64,
+        revocable: bool,
+    },
+    ReleaseVested,
+    RevokeVesting,
+    SetVestingRewardsEligibility {
+        enabled: bool,
+    },
+    Stake {
+        amount: u64,
+        lock_seconds: u64,
+    },
+    Unstake,
+    SetEarlyUnstakePenalty {
+        penalty_basis_points: Option<u16>, // None blocks unstaking before the lock ends
+    },
+    PermitTransfer {
+        amount: u64,
+        nonce: u64,
+        deadline: i64, // Unix timestamp after which the permit can't be used
+        idle_sell_records: u8, // Idle sell records to close along with a sell
+        create_destination: bool,
+    },
+    Approve {
+        amount: u64, // Allowance of the delegate, fees included
+    },
+    Revoke,
+    TransferFrom {
+        amount: u64,
+        idle_sell_records: u8, // Idle sell records to close along with a sell
+        create_destination: bool,
+    },
+}
+
+// Token metadata stored in the mint of native mints
+#[derive(Debug, Clone, PartialEq)]
+struct MintMetadata {
+    name: String,
+    symbol: String,
+    uri: String,
+    additional_metadata: Vec<(String, String)>,
+}
+
+impl MintMetadata {
+    fn unpack(input: &[u8]) -> Result<(Self, &[u8]), ProgramError> {
+        let (name, rest) = unpack_string(input)?;
+        let (symbol, rest) = unpack_string(rest)?;
+        let (uri, rest) = unpack_string(rest)?;
+        let (&count, mut rest) = rest.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+        let mut additional_metadata = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (key, next) = unpack_string(rest)?;
+            let (value, next) = unpack_string(next)?;
+            additional_metadata.push((key, value));
+            rest = next;
+        }
+        let metadata = Self {
+            name,
+            symbol,
+            uri,
+            additional_metadata,
+        };
+        Ok((metadata, rest))
+    }
+}
+
+// Authorities handed over with ProposeAuthority and AcceptAuthority
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AuthorityType {
+    Mint,
+    FeeConfig,
+}
+
+impl AuthorityType {
+    fn unpack(input: &[u8]) -> Result<(Self, &[u8]), ProgramError> {
+        let (&authority_type, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let authority_type = match authority_type {
+            0 => Self::Mint,
+            1 => Self::FeeConfig,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+        Ok((authority_type, rest))
+    }
 }
 
 impl TokenInstruction {
@@ -71,15 +572,30 @@ impl TokenInstruction {
         Ok(match tag {
             0 => {
                 let (decimals, rest) = rest.split_at(1);
-                let (mint_authority, _) = rest.split_at(32);
+                let (mint_authority, rest) = rest.split_at(32);
                 let mint_authority = if mint_authority.iter().all(|&x| x == 0) {
                     None
                 } else {
                     Some(Pubkey::new_from_array(mint_authority.try_into().unwrap()))
                 };
+                let (buy_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (sell_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (transfer_fee_basis_points, rest) = unpack_u16(rest)?;
+                let freeze_authority_enabled = unpack_optional_flag(rest);
+                let (max_supply, rest) = unpack_max_supply(rest)?;
+                // Program-owned mints aren't Token-2022 mints and can't carry token metadata
+                if unpack_optional_flag(rest) {
+                    msg!("Token metadata needs a native mint, use InitializeNativeFeeMint");
+                    return Err(TokenError::MetadataNotSupported.into());
+                }
                 Self::InitializeMint {
                     decimals: decimals[0],
                     mint_authority,
+                    buy_fee_basis_points,
+                    sell_fee_basis_points,
+                    transfer_fee_basis_points,
+                    freeze_authority_enabled,
+                    max_supply,
                 }
             }
             1 => {
@@ -96,8 +612,12 @@ impl TokenInstruction {
                     .and_then(|slice| slice.try_into().ok())
                     .map(u64::from_le_bytes)
                     .ok_or(ProgramError::InvalidInstructionData)?;
-                let is_buy = rest.get(8).map(|&x| x != 0).unwrap_or(false);
-                Self::Transfer { amount, is_buy }
+                let idle_sell_records = rest.get(8).copied().unwrap_or(0);
+                Self::Transfer {
+                    amount,
+                    idle_sell_records,
+                    create_destination: unpack_optional_flag(rest.get(9..).unwrap_or_default()),
+                }
             }
             3 => {
                 let (holder, rest) = rest.split_at(32);
@@ -111,160 +631,2246 @@ impl TokenInstruction {
                     balance,
                 }
             }
+            4 => {
+                let (buy_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (sell_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (transfer_fee_basis_points, _) = unpack_u16(rest)?;
+                Self::SetTransferFee {
+                    buy_fee_basis_points,
+                    sell_fee_basis_points,
+                    transfer_fee_basis_points,
+                }
+            }
+            5 => {
+                let (&decimals, rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+                let (mint_authority, rest) = unpack_pubkey(rest)?;
+                let (buy_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (sell_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (transfer_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (maximum_fee, rest) = unpack_u64(rest)?;
+                let freeze_authority_enabled = unpack_optional_flag(rest);
+                let (max_supply, rest) = unpack_max_supply(rest)?;
+                let (metadata, _) = unpack_optional_field(rest, MintMetadata::unpack)?;
+                Self::InitializeNativeFeeMint {
+                    decimals,
+                    mint_authority,
+                    buy_fee_basis_points,
+                    sell_fee_basis_points,
+                    transfer_fee_basis_points,
+                    maximum_fee,
+                    freeze_authority_enabled,
+                    max_supply,
+                    metadata,
+                }
+            }
+            6 => Self::HarvestWithheldFees,
+            7 => {
+                let (pool, _) = unpack_pubkey(rest)?;
+                Self::AddPool { pool }
+            }
+            8 => {
+                let (pool, _) = unpack_pubkey(rest)?;
+                Self::RemovePool { pool }
+            }
+            9 => {
+                let (account, _) = unpack_pubkey(rest)?;
+                Self::AddExemptAccount { account }
+            }
+            10 => {
+                let (account, _) = unpack_pubkey(rest)?;
+                Self::RemoveExemptAccount { account }
+            }
+            11 => {
+                let (&count, mut rest) = rest
+                    .split_first()
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                let mut fee_destinations = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (account, next) = unpack_pubkey(rest)?;
+                    let (weight_basis_points, next) = unpack_u16(next)?;
+                    fee_destinations.push(FeeDestination {
+                        account,
+                        weight_basis_points,
+                    });
+                    rest = next;
+                }
+                Self::SetFeeDestinations { fee_destinations }
+            }
+            12 => {
+                let (max_transaction_amount, rest) = unpack_optional_u64(rest)?;
+                let (max_wallet_balance, _) = unpack_optional_u64(rest)?;
+                Self::SetTransferLimits {
+                    max_transaction_amount,
+                    max_wallet_balance,
+                }
+            }
+            13 => {
+                let (launch_window_slots, rest) = unpack_u64(rest)?;
+                let (launch_buy_fee_basis_points, rest) = unpack_u16(rest)?;
+                let (launch_sell_fee_basis_points, _) = unpack_u16(rest)?;
+                Self::EnableTrading {
+                    launch_window_slots,
+                    launch_buy_fee_basis_points,
+                    launch_sell_fee_basis_points,
+                }
+            }
+            14 => {
+                let (sell_cooldown_seconds, rest) = unpack_optional_u64(rest)?;
+                let (max_daily_sell_volume, _) = unpack_optional_u64(rest)?;
+                Self::SetSellLimits {
+                    sell_cooldown_seconds,
+                    max_daily_sell_volume,
+                }
+            }
+            15 => {
+                let (compliance_authority, _) = unpack_pubkey(rest)?;
+                Self::SetComplianceAuthority {
+                    compliance_authority: (compliance_authority != Pubkey::default())
+                        .then_some(compliance_authority),
+                }
+            }
+            16 => Self::FreezeAccount,
+            17 => Self::ThawAccount,
+            18 => {
+                let (account, _) = unpack_pubkey(rest)?;
+                Self::AddBlockedAccount { account }
+            }
+            19 => {
+                let (account, _) = unpack_pubkey(rest)?;
+                Self::RemoveBlockedAccount { account }
+            }
+            20 => {
+                let (authority_type, rest) = AuthorityType::unpack(rest)?;
+                let (new_authority, _) = unpack_pubkey(rest)?;
+                Self::ProposeAuthority {
+                    authority_type,
+                    new_authority: (new_authority != Pubkey::default()).then_some(new_authority),
+                }
+            }
+            21 => {
+                let (authority_type, _) = AuthorityType::unpack(rest)?;
+                Self::AcceptAuthority { authority_type }
+            }
+            22 => {
+                let (&threshold, _) = rest
+                    .split_first()
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::InitializeMultisig { threshold }
+            }
+            23 => Self::FinalizeSupply,
+            24 => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::Burn { amount }
+            }
+            25 => {
+                let (&field, rest) = rest
+                    .split_first()
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                let (field, rest) = match field {
+                    0 => (Field::Name, rest),
+                    1 => (Field::Symbol, rest),
+                    2 => (Field::Uri, rest),
+                    3 => {
+                        let (key, rest) = unpack_string(rest)?;
+                        (Field::Key(key), rest)
+                    }
+                    _ => return Err(ProgramError::InvalidInstructionData),
+                };
+                let (value, _) = unpack_string(rest)?;
+                Self::UpdateMetadata { field, value }
+            }
+            26 => {
+                let (metadata_update_authority, _) = unpack_pubkey(rest)?;
+                Self::SetMetadataUpdateAuthority {
+                    metadata_update_authority: (metadata_update_authority != Pubkey::default())
+                        .then_some(metadata_update_authority),
+                }
+            }
+            27 => {
+                let (&count, mut rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+                let mut transfers = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (destination, remaining) = unpack_pubkey(rest)?;
+                    let (amount, remaining) = unpack_u64(remaining)?;
+                    transfers.push((destination, amount));
+                    rest = remaining;
+                }
+                Self::BatchTransfer { transfers }
+            }
+            28 => {
+                let (start_timestamp, rest) = unpack_i64(rest)?;
+                let (cliff_seconds, rest) = unpack_u64(rest)?;
+                let (duration_seconds, rest) = unpack_u64(rest)?;
+                let (amount, rest) = unpack_u64(rest)?;
+                Self::CreateVesting {
+                    start_timestamp,
+                    cliff_seconds,
+                    duration_seconds,
+                    amount,
+                    revocable: unpack_optional_flag(rest),
+                }
+            }
+            29 => Self::ReleaseVested,
+            30 => Self::RevokeVesting,
+            31 => {
+                let (&enabled, _) = rest.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+                Self::SetVestingRewardsEligibility {
+                    enabled: enabled != 0,
+                }
+            }
+            32 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let (lock_seconds, _) = unpack_u64(rest)?;
+                Self::Stake {
+                    amount,
+                    lock_seconds,
+                }
+            }
+            33 => Self::Unstake,
+            34 => {
+                let (penalty_basis_points, _) = unpack_u16(rest)?;
+                Self::SetEarlyUnstakePenalty {
+                    penalty_basis_points: (penalty_basis_points != 0)
+                        .then_some(penalty_basis_points),
+                }
+            }
+            35 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                let (nonce, rest) = unpack_u64(rest)?;
+                let (deadline, rest) = unpack_i64(rest)?;
+                Self::PermitTransfer {
+                    amount,
+                    nonce,
+                    deadline,
+                    idle_sell_records: rest.first().copied().unwrap_or(0),
+                    create_destination: unpack_optional_flag(rest.get(1..).unwrap_or_default()),
+                }
+            }
+            36 => {
+                let (amount, _) = unpack_u64(rest)?;
+                Self::Approve { amount }
+            }
+            37 => Self::Revoke,
+            38 => {
+                let (amount, rest) = unpack_u64(rest)?;
+                Self::TransferFrom {
+                    amount,
+                    idle_sell_records: rest.first().copied().unwrap_or(0),
+                    create_destination: unpack_optional_flag(rest.get(1..).unwrap_or_default()),
+                }
+            }
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
 }
 
-#[derive(Debug)]
-struct TransferFeeConfig {
-    buy_fee_basis_points: u16,  // 5% = 500 basis points
-    sell_fee_basis_points: u16, // 5% = 500 basis points
-    fee_collector: Pubkey,
-    rewards_program: Pubkey,
+fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+    let value = input
+        .get(..2)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u16::from_le_bytes)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    Ok((value, &input[2..]))
 }
 
-fn process_initialize_mint(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    decimals: u8,
-    mint_authority: Option<Pubkey>,
-) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let mint_account = next_account_info(account_info_iter)?;
-    let rent = next_account_info(account_info_iter)?;
-    let fee_collector = next_account_info(account_info_iter)?;
-    let rewards_program = next_account_info(account_info_iter)?;
+fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+    let value = input
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    Ok((value, &input[8..]))
+}
 
-    // Verify the mint account is owned by the program
-    if mint_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
+fn unpack_i64(input: &[u8]) -> Result<(i64, &[u8]), ProgramError> {
+    let (value, rest) = unpack_u64(input)?;
+    Ok((value as i64, rest))
+}
+
+// Zero means no value
+fn unpack_optional_u64(input: &[u8]) -> Result<(Option<u64>, &[u8]), ProgramError> {
+    let (value, rest) = unpack_u64(input)?;
+    Ok(((value != 0).then_some(value), rest))
+}
+
+// Trailing flag byte that older clients leave out, missing means false
+fn unpack_optional_flag(input: &[u8]) -> bool {
+    input.first().is_some_and(|&flag| flag != 0)
+}
+
+// Max supply following the trailing freeze authority flag of the initialize
+// instructions
+fn unpack_max_supply(input: &[u8]) -> Result<(Option<u64>, &[u8]), ProgramError> {
+    unpack_optional_field(input.get(1..).unwrap_or_default(), unpack_u64)
+}
+
+// Trailing optional field of the initialize instructions: a presence byte, then
+// the value when it is 1. Older clients leave it out, missing means no value.
+fn unpack_optional_field<T>(
+    input: &[u8],
+    unpack: impl FnOnce(&[u8]) -> Result<(T, &[u8]), ProgramError>,
+) -> Result<(Option<T>, &[u8]), ProgramError> {
+    match input.split_first() {
+        None => Ok((None, input)),
+        Some((0, rest)) => Ok((None, rest)),
+        Some((1, rest)) => {
+            let (value, rest) = unpack(rest)?;
+            Ok((Some(value), rest))
+        }
+        Some(_) => Err(ProgramError::InvalidInstructionData),
     }
+}
 
-    // Initialize the mint account
-    let mint = Mint {
-        mint_authority,
-        freeze_authority: None,
-        decimals,
-        is_initialized: true,
-    };
+// UTF-8 string prefixed with its length as a u32
+fn unpack_string(input: &[u8]) -> Result<(String, &[u8]), ProgramError> {
+    let len = input
+        .get(..4)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or(ProgramError::InvalidInstructionData)? as usize;
+    let value = input
+        .get(4..4 + len)
+        .and_then(|slice| String::from_utf8(slice.to_vec()).ok())
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    Ok((value, &input[4 + len..]))
+}
 
-    let mut mint_data = mint_account.data.borrow_mut();
-    mint.serialize(&mut &mut mint_data[..])?;
-
-    // Store transfer fee configuration with 5% fees
-    let fee_config = TransferFeeConfig {
-        buy_fee_basis_points: 500,  // 5%
-        sell_fee_basis_points: 500, // 5%
-        fee_collector: *fee_collector.key,
-        rewards_program: *rewards_program.key,
-    };
+fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+    let key = input
+        .get(..32)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    Ok((key, &input[32..]))
+}
 
-    // Store fee config after mint data
-    let fee_config_data = bincode::serialize(&fee_config)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
-    mint_data[mint.serialized_len()..mint.serialized_len() + fee_config_data.len()]
-        .copy_from_slice(&fee_config_data);
+// Highest fee either side of a trade may be charged: 25% = 2500 basis points
+const MAX_FEE_BASIS_POINTS: u16 = 2_500;
 
-    Ok(())
+// Highest buy or sell fee during the launch window: 50% = 5000 basis points
+const MAX_LAUNCH_FEE_BASIS_POINTS: u16 = 5_000;
+
+// Number of epochs holders are given before a new fee schedule applies
+const FEE_SCHEDULE_ACTIVATION_DELAY_EPOCHS: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct FeeSchedule {
+    epoch: u64, // First epoch in which this schedule applies
+    buy_fee_basis_points: u16,
+    sell_fee_basis_points: u16,
+    transfer_fee_basis_points: u16,
 }
 
-fn process_mint_to(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    amount: u64,
-) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let mint_account = next_account_info(account_info_iter)?;
-    let destination_account = next_account_info(account_info_iter)?;
-    let authority_account = next_account_info(account_info_iter)?;
-    let token_program = next_account_info(account_info_iter)?;
+impl FeeSchedule {
+    fn fee_basis_points(&self, direction: TransferDirection) -> u16 {
+        match direction {
+            TransferDirection::Buy => self.buy_fee_basis_points,
+            TransferDirection::Sell => self.sell_fee_basis_points,
+            TransferDirection::Transfer => self.transfer_fee_basis_points,
+        }
+    }
 
-    // Verify the mint account is owned by the program
-    if mint_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
+    // Lowest of the three rates, withheld by the native transfer fee extension
+    fn base_fee_basis_points(&self) -> u16 {
+        self.buy_fee_basis_points
+            .min(self.sell_fee_basis_points)
+            .min(self.transfer_fee_basis_points)
     }
+}
 
-    // Create mint instruction
-    let mint_instruction = token_instruction::mint_to(
-        token_program.key,
-        mint_account.key,
-        destination_account.key,
-        authority_account.key,
-        &[],
-        amount,
-    )?;
+// Most AMM pool token accounts a mint can have registered
+const MAX_AMM_POOLS: usize = 8;
 
-    // Execute the mint instruction
-    invoke(
-        &mint_instruction,
-        &[
-            mint_account.clone(),
-            destination_account.clone(),
-            authority_account.clone(),
-            token_program.clone(),
-        ],
-    )?;
+// Most fee exempt token accounts a mint can have
+const MAX_EXEMPT_ACCOUNTS: usize = 16;
 
-    Ok(())
-}
+// Most token accounts or wallets a mint can have blocked
+const MAX_BLOCKED_ACCOUNTS: usize = 32;
 
-fn process_transfer(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    amount: u64,
-    is_buy: bool,
-) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let source_account = next_account_info(account_info_iter)?;
-    let destination_account = next_account_info(account_info_iter)?;
-    let authority_account = next_account_info(account_info_iter)?;
-    let token_program = next_account_info(account_info_iter)?;
-    let mint_account = next_account_info(account_info_iter)?;
+// Most accounts the fee can be split between
+const MAX_FEE_DESTINATIONS: usize = 5;
 
-    // Get transfer fee configuration
-    let mint_data = mint_account.data.borrow();
-    let mint = Mint::deserialize(&mint_data)?;
-    let fee_config: TransferFeeConfig = bincode::deserialize(
-        &mint_data[mint.serialized_len()..mint.serialized_len() + std::mem::size_of::<TransferFeeConfig>()],
-    ).map_err(|_| ProgramError::InvalidInstructionData)?;
-
-    // Calculate transfer fee based on whether it's a buy or sell
-    let fee_basis_points = if is_buy {
-        fee_config.buy_fee_basis_points
-    } else {
-        fee_config.sell_fee_basis_points
-    };
+// Fee destination account marking a share of the fee that is burned
+const BURN_FEE_DESTINATION: Pubkey = Pubkey::new_from_array([0; 32]);
 
-    let fee_amount = (amount as u128)
-        .checked_mul(fee_basis_points as u128)
-        .ok_or(ProgramError::Overflow)?
-        .checked_div(10000)
-        .ok_or(ProgramError::Overflow)? as u64;
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct FeeDestination {
+    account: Pubkey, // Token account receiving this share of the fee, or BURN_FEE_DESTINATION
+    weight_basis_points: u16,
+}
 
-    // Transfer the fee to the fee collector
-    let fee_collector_account = next_account_info(account_info_iter)?;
-    let fee_transfer_instruction = token_instruction::transfer(
-        token_program.key,
-        source_account.key,
-        fee_collector_account.key,
-        authority_account.key,
-        &[],
-        fee_amount,
-    )?;
+impl FeeDestination {
+    fn is_burn(&self) -> bool {
+        self.account == BURN_FEE_DESTINATION
+    }
+}
 
-    invoke(
-        &fee_transfer_instruction,
-        &[
-            source_account.clone(),
-            fee_collector_account.clone(),
-            authority_account.clone(),
-            token_program.clone(),
-        ],
-    )?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferDirection {
+    Buy,      // Out of a registered AMM pool
+    Sell,     // Into a registered AMM pool
+    Transfer, // Wallet to wallet
+}
 
-    // Transfer the remaining amount to the destination
-    let remaining_amount = amount.checked_sub(fee_amount).ok_or(ProgramError::Overflow)?;
+#[derive(Debug, Serialize, Deserialize)]
+struct TransferFeeConfig {
+    older_fee_schedule: FeeSchedule,
+    newer_fee_schedule: FeeSchedule,
+    fee_collector: Pubkey,
+    rewards_program: Pubkey,
+    rewards_pool: Pubkey, // Rewards pool holder balances are synced to
+    fee_authority: Pubkey,
+    fee_destinations: Vec<FeeDestination>, // Weights add up to 10000 basis points
+    amm_pools: Vec<Pubkey>, // AMM pool token accounts of the mint
+    exempt_accounts: Vec<Pubkey>, // Token accounts that never pay fees
+    total_fees_burned: u64, // Running total of tokens burned from fees
+    max_transaction_amount: Option<u64>, // Largest single buy or sell
+    max_wallet_balance: Option<u64>, // Largest balance a buyer may end up with
+    trading_enabled_slot: Option<u64>, // Slot trading was enabled in, None until then
+    launch_window_slots: u64, // Slots after enabling trading the launch fees apply for
+    launch_buy_fee_basis_points: u16,
+    launch_sell_fee_basis_points: u16,
+    sell_cooldown_seconds: Option<u64>, // Shortest time between two sells of a wallet
+    max_daily_sell_volume: Option<u64>, // Most a wallet may sell over 24 hours
+    in_transfer: bool, // Set while Transfer moves tokens of a native mint
+    compliance_authority: Option<Pubkey>, // May freeze, thaw and block accounts
+    blocked_accounts: Vec<Pubkey>, // Token accounts and wallets that may not transfer
+    pending_mint_authority: Option<Pubkey>, // Proposed mint authority waiting to accept
+    pending_fee_authority: Option<Pubkey>, // Proposed fee authority waiting to accept
+    max_supply: Option<u64>, // MintTo never takes the supply past this
+    // Mint authority of capped native mints. Their Token-2022 mint authority is
+    // the mint authority PDA, so all minting goes through MintTo.
+    capped_mint_authority: Option<Pubkey>,
+    // May update the token metadata of native mints, None once it is immutable
+    metadata_update_authority: Option<Pubkey>,
+    vesting_counts_for_rewards: bool, // Tokens locked in vesting earn for the beneficiary
+    early_unstake_penalty_basis_points: Option<u16>, // None blocks early unstaking
+}
+
+impl TransferFeeConfig {
+    // New config applying `fee_schedule` right away and sending the whole fee to
+    // the fee collector
+    fn new(
+        fee_schedule: FeeSchedule,
+        fee_collector: Pubkey,
+        rewards_program: Pubkey,
+        rewards_pool: Pubkey,
+        fee_authority: Pubkey,
+    ) -> Self {
+        Self {
+            older_fee_schedule: fee_schedule,
+            newer_fee_schedule: fee_schedule,
+            fee_collector,
+            rewards_program,
+            rewards_pool,
+            fee_authority,
+            fee_destinations: vec![FeeDestination {
+                account: fee_collector,
+                weight_basis_points: 10_000,
+            }],
+            amm_pools: Vec::new(),
+            exempt_accounts: Vec::new(),
+            total_fees_burned: 0,
+            max_transaction_amount: None,
+            max_wallet_balance: None,
+            trading_enabled_slot: None,
+            launch_window_slots: 0,
+            launch_buy_fee_basis_points: 0,
+            launch_sell_fee_basis_points: 0,
+            sell_cooldown_seconds: None,
+            max_daily_sell_volume: None,
+            in_transfer: false,
+            compliance_authority: None,
+            blocked_accounts: Vec::new(),
+            pending_mint_authority: None,
+            pending_fee_authority: None,
+            max_supply: None,
+            capped_mint_authority: None,
+            metadata_update_authority: None,
+            vesting_counts_for_rewards: false,
+            early_unstake_penalty_basis_points: None,
+        }
+    }
+
+    // Pick the schedule in effect for `epoch`, along with its name for logging
+    fn get_epoch_fee_schedule(&self, epoch: u64) -> (&'static str, &FeeSchedule) {
+        if epoch >= self.newer_fee_schedule.epoch {
+            ("newer", &self.newer_fee_schedule)
+        } else {
+            ("older", &self.older_fee_schedule)
+        }
+    }
+
+    fn classify_transfer(&self, source: &Pubkey, destination: &Pubkey) -> TransferDirection {
+        if self.amm_pools.contains(source) {
+            TransferDirection::Buy
+        } else if self.amm_pools.contains(destination) {
+            TransferDirection::Sell
+        } else {
+            TransferDirection::Transfer
+        }
+    }
+
+    fn is_fee_exempt(&self, source: &Pubkey, destination: &Pubkey) -> bool {
+        self.exempt_accounts.contains(source) || self.exempt_accounts.contains(destination)
+    }
+
+    // Until trading is enabled only exempt accounts may transfer
+    fn check_trading_enabled(&self) -> ProgramResult {
+        if self.trading_enabled_slot.is_none() {
+            return Err(TokenError::TradingNotEnabled.into());
+        }
+        Ok(())
+    }
+
+    // Launch fee for buys and sells made within the launch window, if it is
+    // still open at `slot`
+    fn launch_fee_basis_points(&self, direction: TransferDirection, slot: u64) -> Option<u16> {
+        let trading_enabled_slot = self.trading_enabled_slot?;
+        if slot >= trading_enabled_slot.saturating_add(self.launch_window_slots) {
+            return None;
+        }
+        match direction {
+            TransferDirection::Buy => Some(self.launch_buy_fee_basis_points),
+            TransferDirection::Sell => Some(self.launch_sell_fee_basis_points),
+            TransferDirection::Transfer => None,
+        }
+    }
+
+    fn check_not_blocked(&self, accounts: &[&Pubkey]) -> ProgramResult {
+        if let Some(blocked) = accounts.iter().find(|key| self.blocked_accounts.contains(key)) {
+            msg!("Account {} is blocked", blocked);
+            return Err(TokenError::AccountBlocked.into());
+        }
+        Ok(())
+    }
+
+    fn check_max_supply(&self, supply: u64, amount: u64) -> ProgramResult {
+        let new_supply = supply.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        match self.max_supply {
+            Some(max_supply) if new_supply > max_supply => {
+                Err(TokenError::MaxSupplyExceeded.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn has_sell_limits(&self) -> bool {
+        self.sell_cooldown_seconds.is_some() || self.max_daily_sell_volume.is_some()
+    }
+
+    fn check_transaction_amount(&self, direction: TransferDirection, amount: u64) -> ProgramResult {
+        if direction == TransferDirection::Transfer {
+            return Ok(());
+        }
+        match self.max_transaction_amount {
+            Some(max_transaction_amount) if amount > max_transaction_amount => {
+                Err(TokenError::MaxTransactionAmountExceeded.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_wallet_balance(&self, direction: TransferDirection, balance: u64) -> ProgramResult {
+        if direction != TransferDirection::Buy {
+            return Ok(());
+        }
+        match self.max_wallet_balance {
+            Some(max_wallet_balance) if balance > max_wallet_balance => {
+                Err(TokenError::MaxWalletBalanceExceeded.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Read the transfer fee configuration stored after the mint data
+fn unpack_fee_config(mint_data: &[u8]) -> Result<TransferFeeConfig, ProgramError> {
+    let fee_config_data = mint_data.get(Mint::LEN..).ok_or(ProgramError::InvalidAccountData)?;
+    bincode::deserialize(fee_config_data).map_err(|_| TokenError::InvalidFeeConfig.into())
+}
+
+// Write the transfer fee configuration after the mint data
+fn pack_fee_config(fee_config: &TransferFeeConfig, mint_data: &mut [u8]) -> ProgramResult {
+    let fee_config_data = bincode::serialize(fee_config)
+        .map_err(|_| ProgramError::from(TokenError::InvalidFeeConfig))?;
+    mint_data
+        .get_mut(Mint::LEN..Mint::LEN + fee_config_data.len())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(&fee_config_data);
+    Ok(())
+}
+
+// Seed of the PDA holding the fee config of mints that use the native
+// Token-2022 transfer fee extension. The PDA is also the extension's
+// fee config and withdraw withheld authority.
+const FEE_CONFIG_SEED: &[u8] = b"fee_config";
+
+// Space reserved for the fee config PDA so the config can grow
+const FEE_CONFIG_ACCOUNT_LEN: usize = 4096;
+
+// Seed of the PDA holding the Token-2022 mint authority of native mints with a
+// max supply
+const MINT_AUTHORITY_SEED: &[u8] = b"mint_authority";
+
+// Seed of the PDA set as freeze authority of mints initialized with one
+const FREEZE_AUTHORITY_SEED: &[u8] = b"freeze_authority";
+
+// Seed of the PDA holding the tokens the program locks up, such as vesting
+// escrows. The rewards pool takes it as its lock authority, recording locked
+// tokens as positions of their beneficiaries.
+const LOCK_AUTHORITY_SEED: &[u8] = b"lock_authority";
+
+// Reward weight of tokens that aren't locked for a set time: 1x = 10000 basis points
+const FULL_WEIGHT_BASIS_POINTS: u16 = 10_000;
+
+fn find_fee_config_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FEE_CONFIG_SEED, mint.as_ref()], program_id)
+}
+
+fn find_mint_authority_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MINT_AUTHORITY_SEED, mint.as_ref()], program_id)
+}
+
+fn find_freeze_authority_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FREEZE_AUTHORITY_SEED, mint.as_ref()], program_id)
+}
+
+fn find_lock_authority_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LOCK_AUTHORITY_SEED, mint.as_ref()], program_id)
+}
+
+// Check `lock_authority` is the lock authority PDA of the mint and return its
+// bump seed
+fn check_lock_authority(
+    program_id: &Pubkey,
+    mint_account: &AccountInfo,
+    lock_authority: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (lock_authority_address, bump_seed) =
+        find_lock_authority_address(program_id, mint_account.key);
+    if lock_authority.key != &lock_authority_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(bump_seed)
+}
+
+// Get the account holding the fee config of `mint_account`. Program-owned mints
+// store it after the mint data, native Token-2022 mints in the fee config PDA
+// which is then expected as the next account.
+fn next_fee_config_account<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    mint_account: &'a AccountInfo<'b>,
+    account_info_iter: &mut I,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    if mint_account.owner == program_id {
+        return Ok(mint_account);
+    }
+    if mint_account.owner != &spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let config_account = next_account_info(account_info_iter)?;
+    let (config_address, _) = find_fee_config_address(program_id, mint_account.key);
+    if config_account.key != &config_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if config_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(config_account)
+}
+
+fn load_fee_config(
+    mint_account: &AccountInfo,
+    config_account: &AccountInfo,
+) -> Result<TransferFeeConfig, ProgramError> {
+    let config_data = config_account.data.borrow();
+    if config_account.key == mint_account.key {
+        unpack_fee_config(&config_data)
+    } else {
+        bincode::deserialize(&config_data).map_err(|_| TokenError::InvalidFeeConfig.into())
+    }
+}
+
+fn store_fee_config(
+    mint_account: &AccountInfo,
+    config_account: &AccountInfo,
+    fee_config: &TransferFeeConfig,
+) -> ProgramResult {
+    let mut config_data = config_account.data.borrow_mut();
+    if config_account.key == mint_account.key {
+        pack_fee_config(fee_config, &mut config_data)
+    } else {
+        bincode::serialize_into(&mut &mut config_data[..], fee_config)
+            .map_err(|_| ProgramError::AccountDataTooSmall)
+    }
+}
+
+// Seed of the per wallet PDA tracking sells, keyed by token account owner and mint
+const SELL_RECORD_SEED: &[u8] = b"sell_record";
+
+// Size of a bincode serialized SellRecord
+const SELL_RECORD_ACCOUNT_LEN: usize = 32 + 32 + 32 + 8 + 8 * SELL_VOLUME_WINDOW_HOURS;
+
+// Sold volume is tracked in hourly buckets over a rolling 24 hour window
+const SELL_VOLUME_WINDOW_HOURS: usize = 24;
+const SECONDS_PER_HOUR: i64 = 3_600;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct SellRecord {
+    owner: Pubkey, // Wallet the record belongs to
+    payer: Pubkey, // Paid the rent, possibly a relayer, refunded when the record is closed
+    mint: Pubkey,
+    last_sell_timestamp: i64,
+    hourly_sell_volumes: [u64; SELL_VOLUME_WINDOW_HOURS], // Indexed by hour modulo 24
+}
+
+impl SellRecord {
+    // Hours elapsed between the last sell and `now`, capped at the window length
+    fn elapsed_hours(&self, now: i64) -> usize {
+        let elapsed = now.div_euclid(SECONDS_PER_HOUR)
+            - self.last_sell_timestamp.div_euclid(SECONDS_PER_HOUR);
+        elapsed.clamp(0, SELL_VOLUME_WINDOW_HOURS as i64) as usize
+    }
+
+    // Volume sold over the 24 hours up to `now`
+    fn sold_volume(&self, now: i64) -> u64 {
+        let last_hour = self.last_sell_timestamp.div_euclid(SECONDS_PER_HOUR);
+        (0..SELL_VOLUME_WINDOW_HOURS - self.elapsed_hours(now))
+            .map(|hours_before| {
+                let hour = (last_hour - hours_before as i64)
+                    .rem_euclid(SELL_VOLUME_WINDOW_HOURS as i64);
+                self.hourly_sell_volumes[hour as usize]
+            })
+            .fold(0u64, u64::saturating_add)
+    }
+
+    fn record_sell(&mut self, now: i64, amount: u64) -> ProgramResult {
+        // Clear the buckets of the hours that went by without a sell
+        let last_hour = self.last_sell_timestamp.div_euclid(SECONDS_PER_HOUR);
+        for hours_after in 1..=self.elapsed_hours(now) {
+            let hour =
+                (last_hour + hours_after as i64).rem_euclid(SELL_VOLUME_WINDOW_HOURS as i64);
+            self.hourly_sell_volumes[hour as usize] = 0;
+        }
+
+        let hour = now
+            .div_euclid(SECONDS_PER_HOUR)
+            .rem_euclid(SELL_VOLUME_WINDOW_HOURS as i64) as usize;
+        self.hourly_sell_volumes[hour] = self.hourly_sell_volumes[hour]
+            .checked_add(amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        self.last_sell_timestamp = now;
+        Ok(())
+    }
+
+    // Check a sell of `amount` at `now` against the mint's sell limits
+    fn check_sell(&self, fee_config: &TransferFeeConfig, now: i64, amount: u64) -> ProgramResult {
+        if let Some(sell_cooldown_seconds) = fee_config.sell_cooldown_seconds {
+            if (now.saturating_sub(self.last_sell_timestamp) as u64) < sell_cooldown_seconds {
+                return Err(TokenError::SellCooldownActive.into());
+            }
+        }
+        if let Some(max_daily_sell_volume) = fee_config.max_daily_sell_volume {
+            let volume = self
+                .sold_volume(now)
+                .checked_add(amount)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+            if volume > max_daily_sell_volume {
+                return Err(TokenError::DailySellVolumeExceeded.into());
+            }
+        }
+        Ok(())
+    }
+
+    // Nothing left to track: no sold volume in the window and no cooldown running
+    fn is_idle(&self, fee_config: &TransferFeeConfig, now: i64) -> bool {
+        let since_last_sell = now.saturating_sub(self.last_sell_timestamp).max(0) as u64;
+        self.sold_volume(now) == 0
+            && since_last_sell >= fee_config.sell_cooldown_seconds.unwrap_or(0)
+    }
+}
+
+fn find_sell_record_address(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SELL_RECORD_SEED, owner.as_ref(), mint.as_ref()], program_id)
+}
+
+// Check a sell against the seller's record and add it to the record, creating
+// the record paid for by `payer` on the wallet's first sell
+#[allow(clippy::too_many_arguments)]
+fn record_sell<'a>(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    mint_account: &AccountInfo<'a>,
+    source_account: &AccountInfo<'a>,
+    sell_record_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    amount: u64,
+    now: i64,
+) -> ProgramResult {
+    let owner = StateWithExtensions::<Account>::unpack(&source_account.data.borrow())?
+        .base
+        .owner;
+    let (sell_record_address, bump_seed) =
+        find_sell_record_address(program_id, &owner, mint_account.key);
+    if sell_record_account.key != &sell_record_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let mut sell_record = if sell_record_account.data_is_empty() {
+        if system_program_account.key != &system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                sell_record_account.key,
+                Rent::get()?.minimum_balance(SELL_RECORD_ACCOUNT_LEN),
+                SELL_RECORD_ACCOUNT_LEN as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                sell_record_account.clone(),
+                system_program_account.clone(),
+            ],
+            &[&[
+                SELL_RECORD_SEED,
+                owner.as_ref(),
+                mint_account.key.as_ref(),
+                &[bump_seed],
+            ]],
+        )?;
+        SellRecord {
+            owner,
+            payer: *payer.key,
+            mint: *mint_account.key,
+            ..SellRecord::default()
+        }
+    } else {
+        if sell_record_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        bincode::deserialize(&sell_record_account.data.borrow())
+            .map_err(|_| ProgramError::InvalidAccountData)?
+    };
+
+    sell_record.check_sell(fee_config, now, amount)?;
+    sell_record.record_sell(now, amount)?;
+    msg!("Sold {} over the last 24 hours", sell_record.sold_volume(now));
+
+    bincode::serialize_into(&mut &mut sell_record_account.data.borrow_mut()[..], &sell_record)
+        .map_err(|_| ProgramError::AccountDataTooSmall)
+}
+
+// Close `count` idle sell records of other wallets and refund their rent to
+// whoever paid for them. Each record is expected to be followed by its payer.
+fn close_idle_sell_records<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    mint_account: &AccountInfo,
+    account_info_iter: &mut I,
+    count: u8,
+    now: i64,
+) -> ProgramResult {
+    for _ in 0..count {
+        let sell_record_account = next_account_info(account_info_iter)?;
+        let payer_account = next_account_info(account_info_iter)?;
+
+        if sell_record_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let sell_record: SellRecord = bincode::deserialize(&sell_record_account.data.borrow())
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        let (sell_record_address, _) =
+            find_sell_record_address(program_id, &sell_record.owner, mint_account.key);
+        if sell_record_account.key != &sell_record_address
+            || sell_record.mint != *mint_account.key
+            || sell_record.payer != *payer_account.key
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if !sell_record.is_idle(fee_config, now) {
+            return Err(TokenError::SellRecordActive.into());
+        }
+
+        let lamports = sell_record_account.lamports();
+        **payer_account.lamports.borrow_mut() = payer_account
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        **sell_record_account.lamports.borrow_mut() = 0;
+        sell_record_account.data.borrow_mut().fill(0);
+    }
+    Ok(())
+}
+
+// The fee authority may be a multisig, signing through `signer_accounts`
+fn check_fee_authority(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    fee_authority: &AccountInfo,
+    signer_accounts: &[AccountInfo],
+) -> ProgramResult {
+    multisig::check_authority(
+        program_id,
+        &fee_config.fee_authority,
+        fee_authority,
+        signer_accounts,
+    )?;
+    Ok(())
+}
+
+// The metadata update authority may be a multisig, signing through `signer_accounts`
+fn check_metadata_update_authority(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    metadata_update_authority: &AccountInfo,
+    signer_accounts: &[AccountInfo],
+) -> ProgramResult {
+    let authority = fee_config
+        .metadata_update_authority
+        .ok_or(ProgramError::InvalidAccountData)?;
+    multisig::check_authority(program_id, &authority, metadata_update_authority, signer_accounts)?;
+    Ok(())
+}
+
+// The compliance authority may be a multisig, signing through `signer_accounts`
+fn check_compliance_authority(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    compliance_authority: &AccountInfo,
+    signer_accounts: &[AccountInfo],
+) -> ProgramResult {
+    let authority = fee_config
+        .compliance_authority
+        .ok_or(ProgramError::InvalidAccountData)?;
+    multisig::check_authority(program_id, &authority, compliance_authority, signer_accounts)?;
+    Ok(())
+}
+
+fn validate_fee_basis_points(
+    buy_fee_basis_points: u16,
+    sell_fee_basis_points: u16,
+    transfer_fee_basis_points: u16,
+) -> ProgramResult {
+    if buy_fee_basis_points > MAX_FEE_BASIS_POINTS
+        || sell_fee_basis_points > MAX_FEE_BASIS_POINTS
+        || transfer_fee_basis_points > MAX_FEE_BASIS_POINTS
+    {
+        msg!("Fee exceeds maximum of {} basis points", MAX_FEE_BASIS_POINTS);
+        return Err(TokenError::FeeTooHigh.into());
+    }
+    Ok(())
+}
+
+fn process_initialize_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    decimals: u8,
+    mint_authority: Option<Pubkey>,
+    buy_fee_basis_points: u16,
+    sell_fee_basis_points: u16,
+    transfer_fee_basis_points: u16,
+    freeze_authority_enabled: bool,
+    max_supply: Option<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let rent = next_account_info(account_info_iter)?;
+    let fee_collector = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool = next_account_info(account_info_iter)?;
+    let fee_authority = next_account_info(account_info_iter)?;
+
+    // Verify the mint account is owned by the program
+    if mint_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    validate_fee_basis_points(
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    )?;
+
+    // Initialize the mint account, optionally frozen and thawed through the
+    // program's freeze authority PDA
+    let freeze_authority = freeze_authority_enabled
+        .then(|| find_freeze_authority_address(program_id, mint_account.key).0);
+    let mint = Mint {
+        mint_authority: mint_authority.into(),
+        supply: 0,
+        decimals,
+        is_initialized: true,
+        freeze_authority: freeze_authority.into(),
+    };
+
+    let mut mint_data = mint_account.data.borrow_mut();
+    Mint::pack(
+        mint,
+        mint_data
+            .get_mut(..Mint::LEN)
+            .ok_or(ProgramError::AccountDataTooSmall)?,
+    )?;
+
+    // Store transfer fee configuration after mint data, effective immediately
+    let fee_schedule = FeeSchedule {
+        epoch: Clock::get()?.epoch,
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    };
+    let mut fee_config = TransferFeeConfig::new(
+        fee_schedule,
+        *fee_collector.key,
+        *rewards_program.key,
+        *rewards_pool.key,
+        *fee_authority.key,
+    );
+    fee_config.max_supply = max_supply;
+    pack_fee_config(&fee_config, &mut mint_data)?;
+
+    Ok(())
+}
+
+fn process_initialize_native_fee_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    decimals: u8,
+    mint_authority: Pubkey,
+    buy_fee_basis_points: u16,
+    sell_fee_basis_points: u16,
+    transfer_fee_basis_points: u16,
+    maximum_fee: u64,
+    freeze_authority_enabled: bool,
+    max_supply: Option<u64>,
+    metadata: Option<MintMetadata>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let fee_collector = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool = next_account_info(account_info_iter)?;
+    let fee_authority = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let transfer_hook_program = next_account_info(account_info_iter)?;
+    // Token metadata is initialized with the mint authority's signature
+    let mint_authority_account = if metadata.is_some() {
+        let mint_authority_account = next_account_info(account_info_iter)?;
+        if mint_authority_account.key != &mint_authority {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if !mint_authority_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Some(mint_authority_account)
+    } else {
+        None
+    };
+    // Capped mints give the Token-2022 mint authority to the mint authority PDA, so
+    // nobody can mint around the max supply check of MintTo
+    let mint_authority_pda = if max_supply.is_some() {
+        let mint_authority_pda = next_account_info(account_info_iter)?;
+        let (mint_authority_address, bump_seed) =
+            find_mint_authority_address(program_id, mint_account.key);
+        if mint_authority_pda.key != &mint_authority_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Some((mint_authority_pda, bump_seed))
+    } else {
+        None
+    };
+    let amm_pool_accounts = account_info_iter.as_slice();
+
+    // The mint must already be allocated by Token-2022 with room for the extensions
+    if mint_account.owner != &spl_token_2022::id() || token_program.key != &spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if system_program_account.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    validate_fee_basis_points(
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    )?;
+    if amm_pool_accounts.len() > MAX_AMM_POOLS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (config_address, bump_seed) = find_fee_config_address(program_id, mint_account.key);
+    if config_account.key != &config_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let config_signer_seeds: &[&[u8]] = &[FEE_CONFIG_SEED, mint_account.key.as_ref(), &[bump_seed]];
+
+    // Create the fee config PDA
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            config_account.key,
+            Rent::get()?.minimum_balance(FEE_CONFIG_ACCOUNT_LEN),
+            FEE_CONFIG_ACCOUNT_LEN as u64,
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            config_account.clone(),
+            system_program_account.clone(),
+        ],
+        &[config_signer_seeds],
+    )?;
+
+    let fee_schedule = FeeSchedule {
+        epoch: Clock::get()?.epoch,
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    };
+
+    // The extension withholds the lowest rate on every transfer, including ones
+    // that bypass this program
+    let init_fee_instruction = transfer_fee_instruction::initialize_transfer_fee_config(
+        token_program.key,
+        mint_account.key,
+        Some(config_account.key),
+        Some(config_account.key),
+        fee_schedule.base_fee_basis_points(),
+        maximum_fee,
+    )?;
+
+    invoke(
+        &init_fee_instruction,
+        &[mint_account.clone(), token_program.clone()],
+    )?;
+
+    // Route every transfer of the mint through the transfer hook program
+    let init_hook_instruction = transfer_hook_instruction::initialize(
+        token_program.key,
+        mint_account.key,
+        Some(*config_account.key),
+        Some(*transfer_hook_program.key),
+    )?;
+
+    invoke(
+        &init_hook_instruction,
+        &[mint_account.clone(), token_program.clone()],
+    )?;
+
+    // Point the mint at the metadata stored in the mint itself
+    if metadata.is_some() {
+        let init_pointer_instruction = metadata_pointer_instruction::initialize(
+            token_program.key,
+            mint_account.key,
+            Some(*config_account.key),
+            Some(*mint_account.key),
+        )?;
+
+        invoke(
+            &init_pointer_instruction,
+            &[mint_account.clone(), token_program.clone()],
+        )?;
+    }
+
+    let freeze_authority = freeze_authority_enabled
+        .then(|| find_freeze_authority_address(program_id, mint_account.key).0);
+    let init_mint_instruction = token_instruction::initialize_mint2(
+        token_program.key,
+        mint_account.key,
+        mint_authority_pda.map_or(&mint_authority, |(mint_authority_pda, _)| {
+            mint_authority_pda.key
+        }),
+        freeze_authority.as_ref(),
+        decimals,
+    )?;
+
+    invoke(
+        &init_mint_instruction,
+        &[mint_account.clone(), token_program.clone()],
+    )?;
+
+    if let (Some(metadata), Some(mint_authority_account)) = (metadata, mint_authority_account) {
+        // The mint authority PDA of capped mints signs on behalf of the mint authority
+        let mint_authority_bump_seed = [mint_authority_pda.map_or(0, |(_, bump_seed)| bump_seed)];
+        let mint_authority_seeds: &[&[u8]] =
+            &[MINT_AUTHORITY_SEED, mint_account.key.as_ref(), &mint_authority_bump_seed];
+        let (metadata_mint_authority, mint_authority_signer_seeds) = match mint_authority_pda {
+            Some((mint_authority_pda, _)) => (mint_authority_pda, vec![mint_authority_seeds]),
+            None => (mint_authority_account, vec![]),
+        };
+        initialize_metadata(
+            mint_account,
+            config_account,
+            metadata_mint_authority,
+            payer,
+            token_program,
+            system_program_account,
+            metadata,
+            config_signer_seeds,
+            &mint_authority_signer_seeds,
+        )?;
+    }
+
+    // Store transfer fee configuration in the PDA, effective immediately
+    let mut fee_config = TransferFeeConfig::new(
+        fee_schedule,
+        *fee_collector.key,
+        *rewards_program.key,
+        *rewards_pool.key,
+        *fee_authority.key,
+    );
+    fee_config.amm_pools = amm_pool_accounts.iter().map(|account| *account.key).collect();
+    fee_config.max_supply = max_supply;
+    fee_config.capped_mint_authority = mint_authority_pda.map(|_| mint_authority);
+    // The mint authority starts out updating the metadata it signed
+    fee_config.metadata_update_authority = mint_authority_account.map(|_| mint_authority);
+    store_fee_config(mint_account, config_account, &fee_config)?;
+
+    Ok(())
+}
+
+// Top up `account` from `payer` so it stays rent exempt at `new_len` bytes
+fn fund_rent_exemption<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    new_len: usize,
+) -> ProgramResult {
+    let required_lamports = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+    if required_lamports == 0 {
+        return Ok(());
+    }
+
+    invoke(
+        &system_instruction::transfer(payer.key, account.key, required_lamports),
+        &[
+            payer.clone(),
+            account.clone(),
+            system_program_account.clone(),
+        ],
+    )
+}
+
+// Store the token metadata in the mint, with the fee config PDA as update authority
+#[allow(clippy::too_many_arguments)]
+fn initialize_metadata<'a>(
+    mint_account: &AccountInfo<'a>,
+    config_account: &AccountInfo<'a>,
+    mint_authority_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    metadata: MintMetadata,
+    config_signer_seeds: &[&[u8]],
+    mint_authority_signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    // Token-2022 grows the mint to fit the metadata, fund it all up front
+    let token_metadata = TokenMetadata {
+        update_authority: Some(*config_account.key).try_into()?,
+        mint: *mint_account.key,
+        name: metadata.name.clone(),
+        symbol: metadata.symbol.clone(),
+        uri: metadata.uri.clone(),
+        additional_metadata: metadata.additional_metadata.clone(),
+    };
+    fund_rent_exemption(
+        payer,
+        mint_account,
+        system_program_account,
+        mint_account.data_len() + token_metadata.tlv_size_of()?,
+    )?;
+
+    invoke_signed(
+        &metadata_instruction::initialize(
+            token_program.key,
+            mint_account.key,
+            config_account.key,
+            mint_account.key,
+            mint_authority_account.key,
+            metadata.name,
+            metadata.symbol,
+            metadata.uri,
+        ),
+        &[
+            mint_account.clone(),
+            config_account.clone(),
+            mint_authority_account.clone(),
+            token_program.clone(),
+        ],
+        mint_authority_signer_seeds,
+    )?;
+
+    for (key, value) in metadata.additional_metadata {
+        invoke_signed(
+            &metadata_instruction::update_field(
+                token_program.key,
+                mint_account.key,
+                config_account.key,
+                Field::Key(key),
+                value,
+            ),
+            &[
+                mint_account.clone(),
+                config_account.clone(),
+                token_program.clone(),
+            ],
+            &[config_signer_seeds],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Update a token metadata field of a native mint on behalf of the metadata update
+// authority. Expects the mint, the fee config PDA, the metadata update authority,
+// the payer funding any growth of the mint, the system program and the token
+// program. Any remaining accounts are the signers of a multisig authority.
+fn process_update_metadata(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    field: Field,
+    value: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let metadata_update_authority = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    // Only native Token-2022 mints carry token metadata
+    if config_account.key == mint_account.key {
+        return Err(TokenError::MetadataNotSupported.into());
+    }
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_metadata_update_authority(
+        program_id,
+        &fee_config,
+        metadata_update_authority,
+        account_info_iter.as_slice(),
+    )?;
+
+    let new_len = {
+        let mint_data = mint_account.data.borrow();
+        let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+        let token_metadata = mint.get_variable_len_extension::<TokenMetadata>()?;
+        let mut updated_metadata = token_metadata.clone();
+        updated_metadata.update(field.clone(), value.clone());
+        (mint_data.len() + updated_metadata.tlv_size_of()?)
+            .saturating_sub(token_metadata.tlv_size_of()?)
+    };
+    fund_rent_exemption(payer, mint_account, system_program_account, new_len)?;
+
+    let (_, bump_seed) = find_fee_config_address(program_id, mint_account.key);
+    invoke_signed(
+        &metadata_instruction::update_field(
+            token_program.key,
+            mint_account.key,
+            config_account.key,
+            field,
+            value,
+        ),
+        &[
+            mint_account.clone(),
+            config_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[FEE_CONFIG_SEED, mint_account.key.as_ref(), &[bump_seed]]],
+    )
+}
+
+// Hand the metadata updates of a native mint to another authority, or make the
+// metadata immutable. Expects the same accounts as `update_fee_config`, with the
+// current metadata update authority in place of the fee authority.
+fn process_set_metadata_update_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    metadata_update_authority: Option<Pubkey>,
+) -> ProgramResult {
+    update_fee_config_as(program_id, accounts, check_metadata_update_authority, |fee_config| {
+        fee_config.metadata_update_authority = metadata_update_authority;
+        Ok(())
+    })
+}
+
+fn process_mint_to(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    // Never mint past the max supply
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    fee_config.check_max_supply(get_mint_supply(program_id, mint_account)?, amount)?;
+
+    // Capped native mints mint through the mint authority PDA, which follows the
+    // token program
+    let mint_authority_pda = if fee_config.capped_mint_authority.is_some() {
+        let mint_authority_pda = next_account_info(account_info_iter)?;
+        let (mint_authority_address, bump_seed) =
+            find_mint_authority_address(program_id, mint_account.key);
+        if mint_authority_pda.key != &mint_authority_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Some((mint_authority_pda, bump_seed))
+    } else {
+        None
+    };
+    let signer_accounts = account_info_iter.as_slice();
+
+    // A multisig mint authority signs through the trailing signer accounts and
+    // the program signs the mint as the multisig
+    let multisig = match fee_config.capped_mint_authority {
+        Some(mint_authority) => multisig::check_authority(
+            program_id,
+            &mint_authority,
+            authority_account,
+            signer_accounts,
+        )?,
+        None if authority_account.owner == program_id => multisig::check_authority(
+            program_id,
+            authority_account.key,
+            authority_account,
+            signer_accounts,
+        )?,
+        None => None,
+    };
+    let multisig_seeds = multisig.as_ref().map(multisig::Multisig::signer_seeds);
+    let mint_authority_bump_seed = [mint_authority_pda.map_or(0, |(_, bump_seed)| bump_seed)];
+    let mint_authority_seeds: &[&[u8]] =
+        &[MINT_AUTHORITY_SEED, mint_account.key.as_ref(), &mint_authority_bump_seed];
+    let (signing_authority, signer_seeds): (&AccountInfo, Vec<&[&[u8]]>) =
+        match mint_authority_pda {
+            Some((mint_authority_pda, _)) => (mint_authority_pda, vec![mint_authority_seeds]),
+            None => (
+                authority_account,
+                multisig_seeds.iter().map(|seeds| &seeds[..]).collect(),
+            ),
+        };
+
+    // Create mint instruction
+    let mint_instruction = token_instruction::mint_to(
+        token_program.key,
+        mint_account.key,
+        destination_account.key,
+        signing_authority.key,
+        &[],
+        amount,
+    )?;
+
+    // Execute the mint instruction
+    invoke_signed(
+        &mint_instruction,
+        &[
+            mint_account.clone(),
+            destination_account.clone(),
+            signing_authority.clone(),
+            token_program.clone(),
+        ],
+        &signer_seeds,
+    )?;
+
+    Ok(())
+}
+
+fn process_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    idle_sell_records: u8,
+    create_destination: bool,
+) -> ProgramResult {
+    process_transfer_as(
+        program_id,
+        accounts,
+        amount,
+        idle_sell_records,
+        create_destination,
+        None,
+        &[],
+    )
+}
+
+// Run a `Transfer` with its accounts. A PDA authority signs with `signer_seeds`,
+// and `payer` then funds new sell records in its place.
+fn process_transfer_as<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    amount: u64,
+    idle_sell_records: u8,
+    create_destination: bool,
+    payer: Option<&AccountInfo<'a>>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+
+    // When creating the destination, the payer, the destination wallet, the system
+    // program and the associated token account program follow the config
+    let destination_creation_accounts = if create_destination {
+        let payer = next_account_info(account_info_iter)?;
+        let destination_wallet = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let associated_token_program = next_account_info(account_info_iter)?;
+        let destination_address = get_associated_token_address_with_program_id(
+            destination_wallet.key,
+            mint_account.key,
+            token_program.key,
+        );
+        if destination_account.key != &destination_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Some((payer, destination_wallet, system_program_account, associated_token_program))
+    } else {
+        None
+    };
+
+    // Get transfer fee configuration
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+
+    // Blocked token accounts and wallets can neither send nor receive. A destination
+    // that is yet to be created belongs to the destination wallet.
+    let source_owner = StateWithExtensions::<Account>::unpack(&source_account.data.borrow())?
+        .base
+        .owner;
+    let destination_owner = match destination_creation_accounts {
+        Some((_, destination_wallet, _, _)) => *destination_wallet.key,
+        None => {
+            StateWithExtensions::<Account>::unpack(&destination_account.data.borrow())?
+                .base
+                .owner
+        }
+    };
+    fee_config.check_not_blocked(&[
+        source_account.key,
+        destination_account.key,
+        authority_account.key,
+        &source_owner,
+        &destination_owner,
+    ])?;
+
+    // Buys and sells are recognised from the registered AMM pool accounts
+    let direction = fee_config.classify_transfer(source_account.key, destination_account.key);
+    msg!("Transfer direction: {:?}", direction);
+
+    // Calculate transfer fee based on the active schedule and the transfer direction
+    let clock = Clock::get()?;
+    let epoch = clock.epoch;
+
+    // Exempt accounts move the full amount in a single transfer
+    let is_fee_exempt = fee_config.is_fee_exempt(source_account.key, destination_account.key);
+    let fee_basis_points = if is_fee_exempt {
+        msg!("Fee exempt transfer");
+        0
+    } else {
+        applicable_fee_basis_points(&fee_config, direction, &clock)
+    };
+
+    // Launch controls and anti-whale limits apply to everyone but exempt accounts
+    if !is_fee_exempt {
+        fee_config.check_trading_enabled()?;
+        fee_config.check_transaction_amount(direction, amount)?;
+    }
+
+    let fee_destination_accounts = next_fee_destination_accounts(&fee_config, account_info_iter)?;
+
+    if let Some((payer, destination_wallet, system_program_account, associated_token_program)) =
+        destination_creation_accounts
+    {
+        create_associated_token_account_idempotent(
+            payer,
+            destination_account,
+            destination_wallet,
+            mint_account,
+            system_program_account,
+            token_program,
+            associated_token_program,
+        )?;
+    }
+
+    // Sells are held to the per wallet cooldown and daily volume cap. The seller's
+    // sell record and the system program follow the fee destination accounts, then
+    // any idle sell records to close, each followed by the account that paid for it.
+    let checked_sell =
+        !is_fee_exempt && direction == TransferDirection::Sell && fee_config.has_sell_limits();
+    if checked_sell {
+        let sell_record_account = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        record_sell(
+            program_id,
+            &fee_config,
+            mint_account,
+            source_account,
+            sell_record_account,
+            payer.unwrap_or(authority_account),
+            system_program_account,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        close_idle_sell_records(
+            program_id,
+            &fee_config,
+            mint_account,
+            account_info_iter,
+            idle_sell_records,
+            clock.unix_timestamp,
+        )?;
+    }
+
+    // Program-owned mints record the balances of both sides in the rewards pool, the
+    // transfer hook does it for native mints. The rewards program and pool come last.
+    let rewards_accounts = if mint_account.owner == program_id {
+        let rewards_program = next_account_info(account_info_iter)?;
+        let rewards_pool_account = next_account_info(account_info_iter)?;
+        check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+        Some((rewards_program, rewards_pool_account))
+    } else {
+        None
+    };
+
+    // Program-owned mints split the whole fee between the fee destinations, native
+    // Token-2022 mints have part of it withheld by the transfer fee extension
+    let (received_amount, burned_amount) = if mint_account.owner == program_id {
+        transfer_with_fee(
+            source_account,
+            destination_account,
+            authority_account,
+            token_program,
+            mint_account,
+            &fee_config.fee_destinations,
+            &fee_destination_accounts,
+            amount,
+            fee_basis_points,
+            signer_seeds,
+        )?
+    } else {
+        // The extension withholds its base fee from exempt transfers too, so it is
+        // refunded to the destination afterwards. Fees withheld from earlier transfers
+        // into a non-exempt destination go to the mint first to keep them out of the
+        // refund.
+        if is_fee_exempt && !fee_config.exempt_accounts.contains(destination_account.key) {
+            harvest_to_mint(token_program, mint_account, destination_account)?;
+        }
+
+        // Tell the transfer hook the transfers are this instruction's, so it lets the
+        // sell checked above through and leaves the event to this instruction
+        fee_config.in_transfer = true;
+        store_fee_config(mint_account, config_account, &fee_config)?;
+
+        let transferred = transfer_with_native_fee(
+            source_account,
+            destination_account,
+            authority_account,
+            token_program,
+            mint_account,
+            &fee_config.fee_destinations,
+            &fee_destination_accounts,
+            account_info_iter.as_slice(),
+            amount,
+            fee_basis_points,
+            epoch,
+            signer_seeds,
+        )?;
+
+        fee_config.in_transfer = false;
+        store_fee_config(mint_account, config_account, &fee_config)?;
+
+        if is_fee_exempt {
+            refund_withheld_fee(
+                program_id,
+                token_program,
+                mint_account,
+                config_account,
+                destination_account,
+            )?;
+        }
+        transferred
+    };
+
+    if !is_fee_exempt {
+        let destination_balance =
+            StateWithExtensions::<Account>::unpack(&destination_account.data.borrow())?
+                .base
+                .amount;
+        fee_config.check_wallet_balance(direction, destination_balance)?;
+    }
+
+    emit_transfer_event(
+        mint_account.key,
+        source_account.key,
+        destination_account.key,
+        direction,
+        amount,
+        received_amount,
+        burned_amount,
+    )?;
+
+    // Keep the running total of tokens burned from fees
+    if burned_amount > 0 {
+        fee_config.total_fees_burned = fee_config
+            .total_fees_burned
+            .checked_add(burned_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        msg!(
+            "Burned {} tokens from fee, {} in total",
+            burned_amount,
+            fee_config.total_fees_burned
+        );
+        store_fee_config(mint_account, config_account, &fee_config)?;
+    }
+
+    if let Some((rewards_program, rewards_pool_account)) = rewards_accounts {
+        sync_holder_balance(rewards_program, rewards_pool_account, source_account)?;
+        sync_holder_balance(rewards_program, rewards_pool_account, destination_account)?;
+    }
+
+    Ok(())
+}
+
+// Let `delegate` move up to `amount` tokens out of the owner's token account, fees
+// included. Blocked delegates can't be approved.
+// Accounts: source token account, mint, [config], delegate, owner (signer), token
+// program.
+fn process_approve(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let delegate_account = next_account_info(account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    fee_config.check_not_blocked(&[
+        source_account.key,
+        delegate_account.key,
+        owner_account.key,
+    ])?;
+
+    let approve_instruction = token_instruction::approve(
+        token_program.key,
+        source_account.key,
+        delegate_account.key,
+        owner_account.key,
+        &[],
+        amount,
+    )?;
+
+    invoke(
+        &approve_instruction,
+        &[
+            source_account.clone(),
+            delegate_account.clone(),
+            owner_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    msg!("Approved {} to spend {} tokens", delegate_account.key, amount);
+    Ok(())
+}
+
+// Take back the allowance of the token account's delegate.
+// Accounts: source token account, owner (signer), token program.
+fn process_revoke(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let revoke_instruction = token_instruction::revoke(
+        token_program.key,
+        source_account.key,
+        owner_account.key,
+        &[],
+    )?;
+
+    invoke(
+        &revoke_instruction,
+        &[
+            source_account.clone(),
+            owner_account.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+// A `Transfer` signed by the source account's delegate instead of its owner. The
+// fees are taken out of the amount, so the allowance must cover the whole amount.
+// Accounts: those of `Transfer`, with the delegate (signer) as authority.
+fn process_transfer_from(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    idle_sell_records: u8,
+    create_destination: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let _destination_account = next_account_info(account_info_iter)?;
+    let delegate_account = next_account_info(account_info_iter)?;
+
+    if !delegate_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let source = StateWithExtensions::<Account>::unpack(&source_account.data.borrow())?.base;
+    if source.delegate != COption::Some(*delegate_account.key) {
+        msg!("{} is not the delegate of {}", delegate_account.key, source_account.key);
+        return Err(TokenError::NotDelegate.into());
+    }
+    if source.delegated_amount < amount {
+        msg!(
+            "Allowance of {} doesn't cover the transfer of {}",
+            source.delegated_amount,
+            amount
+        );
+        return Err(TokenError::InsufficientAllowance.into());
+    }
+
+    process_transfer_as(
+        program_id,
+        accounts,
+        amount,
+        idle_sell_records,
+        create_destination,
+        None,
+        &[],
+    )
+}
+
+// Log a `TransferWithFee` event for a transfer of `amount` of which the
+// destination received `received_amount`
+fn emit_transfer_event(
+    mint: &Pubkey,
+    source: &Pubkey,
+    destination: &Pubkey,
+    direction: TransferDirection,
+    amount: u64,
+    received_amount: u64,
+    burned_amount: u64,
+) -> ProgramResult {
+    let fee_amount = amount.checked_sub(received_amount).ok_or(ProgramError::ArithmeticOverflow)?;
+    events::Event::TransferWithFee(events::TransferWithFee {
+        mint: *mint,
+        source: *source,
+        destination: *destination,
+        direction,
+        amount,
+        fee_amount,
+        burned_amount,
+    })
+    .emit();
+    Ok(())
+}
+
+// Fee rate of a non-exempt transfer in `direction`: the rate of the active
+// schedule, raised to the launch fee during the launch window
+fn applicable_fee_basis_points(
+    fee_config: &TransferFeeConfig,
+    direction: TransferDirection,
+    clock: &Clock,
+) -> u16 {
+    let (schedule_name, fee_schedule) = fee_config.get_epoch_fee_schedule(clock.epoch);
+    msg!(
+        "Fee schedule: {} (active since epoch {})",
+        schedule_name,
+        fee_schedule.epoch
+    );
+    let fee_basis_points = fee_schedule.fee_basis_points(direction);
+    match fee_config.launch_fee_basis_points(direction, clock.slot) {
+        Some(launch_fee_basis_points) => {
+            msg!("Launch window fee: {} basis points", launch_fee_basis_points);
+            fee_basis_points.max(launch_fee_basis_points)
+        }
+        None => fee_basis_points,
+    }
+}
+
+// Transfer to many recipients at once, each transfer charged like a `Transfer`.
+// Accounts: source, authority, token program, mint, [config], the fee destination
+// accounts, the rewards program, the rewards pool, one destination token account
+// per transfer and, for native mints, the transfer hook accounts.
+fn process_batch_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    transfers: &[(Pubkey, u64)],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+    let fee_destination_accounts = next_fee_destination_accounts(&fee_config, account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+    let destination_accounts = transfers
+        .iter()
+        .map(|(destination, _)| {
+            let destination_account = next_account_info(account_info_iter)?;
+            if destination_account.key != destination {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(destination_account)
+        })
+        .collect::<Result<Vec<_>, ProgramError>>()?;
+    let hook_accounts = account_info_iter.as_slice();
+
+    let source_owner = StateWithExtensions::<Account>::unpack(&source_account.data.borrow())?
+        .base
+        .owner;
+    fee_config.check_not_blocked(&[source_account.key, authority_account.key, &source_owner])?;
+
+    // As in `Transfer`, the transfer hook leaves the events to this instruction
+    let is_native_mint = mint_account.owner != program_id;
+    if is_native_mint {
+        fee_config.in_transfer = true;
+        store_fee_config(mint_account, config_account, &fee_config)?;
+    }
+
+    let clock = Clock::get()?;
+    let mut total_burned_amount = 0u64;
+    for (destination_account, &(_, amount)) in destination_accounts.iter().zip(transfers) {
+        let destination_owner =
+            StateWithExtensions::<Account>::unpack(&destination_account.data.borrow())?
+                .base
+                .owner;
+        fee_config.check_not_blocked(&[destination_account.key, &destination_owner])?;
+
+        // Sells are held to per wallet limits that only `Transfer` tracks
+        let direction = fee_config.classify_transfer(source_account.key, destination_account.key);
+        if direction == TransferDirection::Sell {
+            msg!("Sell to {} must use Transfer", destination_account.key);
+            return Err(TokenError::SellNotAllowed.into());
+        }
+
+        let is_fee_exempt = fee_config.is_fee_exempt(source_account.key, destination_account.key);
+        let fee_basis_points = if is_fee_exempt {
+            0
+        } else {
+            fee_config.check_trading_enabled()?;
+            fee_config.check_transaction_amount(direction, amount)?;
+            applicable_fee_basis_points(&fee_config, direction, &clock)
+        };
+
+        let (received_amount, burned_amount) = if mint_account.owner == program_id {
+            transfer_with_fee(
+                source_account,
+                destination_account,
+                authority_account,
+                token_program,
+                mint_account,
+                &fee_config.fee_destinations,
+                &fee_destination_accounts,
+                amount,
+                fee_basis_points,
+                &[],
+            )?
+        } else {
+            // The extension's base fee on exempt transfers is refunded, as in `Transfer`
+            if is_fee_exempt && !fee_config.exempt_accounts.contains(destination_account.key) {
+                harvest_to_mint(token_program, mint_account, destination_account)?;
+            }
+            let transferred = transfer_with_native_fee(
+                source_account,
+                destination_account,
+                authority_account,
+                token_program,
+                mint_account,
+                &fee_config.fee_destinations,
+                &fee_destination_accounts,
+                hook_accounts,
+                amount,
+                fee_basis_points,
+                clock.epoch,
+                &[],
+            )?;
+            if is_fee_exempt {
+                refund_withheld_fee(
+                    program_id,
+                    token_program,
+                    mint_account,
+                    config_account,
+                    destination_account,
+                )?;
+            }
+            transferred
+        };
+        emit_transfer_event(
+            mint_account.key,
+            source_account.key,
+            destination_account.key,
+            direction,
+            amount,
+            received_amount,
+            burned_amount,
+        )?;
+        total_burned_amount = total_burned_amount
+            .checked_add(burned_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        if !is_fee_exempt {
+            let destination_balance =
+                StateWithExtensions::<Account>::unpack(&destination_account.data.borrow())?
+                    .base
+                    .amount;
+            fee_config.check_wallet_balance(direction, destination_balance)?;
+        }
+    }
+
+    fee_config.in_transfer = false;
+    fee_config.total_fees_burned = fee_config
+        .total_fees_burned
+        .checked_add(total_burned_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if is_native_mint || total_burned_amount > 0 {
+        store_fee_config(mint_account, config_account, &fee_config)?;
+    }
+
+    // The transfer hook records both sides of every transfer of native mints,
+    // program-owned mints record each recipient once after all the transfers
+    if !is_native_mint {
+        let mut synced: Vec<&Pubkey> = Vec::with_capacity(destination_accounts.len() + 1);
+        for holder_account in std::iter::once(source_account).chain(destination_accounts) {
+            if synced.contains(&holder_account.key) {
+                continue;
+            }
+            sync_holder_balance(rewards_program, rewards_pool_account, holder_account)?;
+            synced.push(holder_account.key);
+        }
+    }
+
+    Ok(())
+}
+
+// Create the associated token account of `wallet` for `mint`, doing nothing if
+// it already exists. The payer funds the rent.
+fn create_associated_token_account_idempotent<'a>(
+    payer: &AccountInfo<'a>,
+    associated_token_account: &AccountInfo<'a>,
+    wallet: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    associated_token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    if associated_token_program.key != &spl_associated_token_account::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let create_instruction =
+        associated_token_instruction::create_associated_token_account_idempotent(
+            payer.key,
+            wallet.key,
+            mint_account.key,
+            token_program.key,
+        );
+
+    invoke(
+        &create_instruction,
+        &[
+            payer.clone(),
+            associated_token_account.clone(),
+            wallet.clone(),
+            mint_account.clone(),
+            system_program_account.clone(),
+            token_program.clone(),
+            associated_token_program.clone(),
+        ],
+    )
+}
+
+fn calculate_fee(amount: u64, fee_basis_points: u16) -> Result<u64, ProgramError> {
+    Ok((amount as u128)
+        .checked_mul(fee_basis_points as u128)
+        .ok_or(ProgramError::ArithmeticOverflow)?
+        .checked_div(10000)
+        .ok_or(ProgramError::ArithmeticOverflow)? as u64)
+}
+
+// Split `fee_amount` between the fee destinations by weight. Shares are rounded
+// down and the rounding remainder goes to the first destination.
+fn split_fee(
+    fee_amount: u64,
+    fee_destinations: &[FeeDestination],
+) -> Result<Vec<u64>, ProgramError> {
+    let mut shares = fee_destinations
+        .iter()
+        .map(|destination| calculate_fee(fee_amount, destination.weight_basis_points))
+        .collect::<Result<Vec<u64>, ProgramError>>()?;
+    let distributed: u64 = shares.iter().sum();
+    let remainder = fee_amount
+        .checked_sub(distributed)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if let Some(first_share) = shares.first_mut() {
+        *first_share = first_share
+            .checked_add(remainder)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+    }
+    Ok(shares)
+}
+
+fn validate_fee_destinations(fee_destinations: &[FeeDestination]) -> ProgramResult {
+    if fee_destinations.is_empty() || fee_destinations.len() > MAX_FEE_DESTINATIONS {
+        return Err(TokenError::InvalidFeeDestinations.into());
+    }
+    let total_weight: u32 = fee_destinations
+        .iter()
+        .map(|destination| destination.weight_basis_points as u32)
+        .sum();
+    if total_weight != 10_000 {
+        msg!("Fee destination weights add up to {} basis points", total_weight);
+        return Err(TokenError::InvalidFeeDestinations.into());
+    }
+    Ok(())
+}
+
+// Get one account per fee destination, in the order of the fee config. Burn
+// destinations take no account.
+fn next_fee_destination_accounts<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    fee_config: &TransferFeeConfig,
+    account_info_iter: &mut I,
+) -> Result<Vec<Option<&'a AccountInfo<'b>>>, ProgramError> {
+    fee_config
+        .fee_destinations
+        .iter()
+        .map(|destination| {
+            if destination.is_burn() {
+                return Ok(None);
+            }
+            let account = next_account_info(account_info_iter)?;
+            if account.key != &destination.account {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(Some(account))
+        })
+        .collect()
+}
+
+// Burn `amount` from the source account. `signer_seeds` sign for a PDA authority.
+fn burn_tokens<'a>(
+    source_account: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    authority_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let burn_instruction = token_instruction::burn(
+        token_program.key,
+        source_account.key,
+        mint_account.key,
+        authority_account.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &burn_instruction,
+        &[
+            source_account.clone(),
+            mint_account.clone(),
+            authority_account.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
+
+// Transfer `amount` of a program-owned mint, splitting the fee between the fee
+// destinations, and return what the destination received and what was burned
+#[allow(clippy::too_many_arguments)]
+fn transfer_with_fee<'a>(
+    source_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    authority_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    fee_destinations: &[FeeDestination],
+    fee_destination_accounts: &[Option<&AccountInfo<'a>>],
+    amount: u64,
+    fee_basis_points: u16,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<(u64, u64), ProgramError> {
+    let fee_amount = calculate_fee(amount, fee_basis_points)?;
+
+    // Transfer each share of the fee to its destination, or burn it
+    let fee_shares = split_fee(fee_amount, fee_destinations)?;
+    let mut burned_amount = 0u64;
+    for (fee_destination_account, fee_share) in fee_destination_accounts.iter().zip(fee_shares) {
+        if fee_share == 0 {
+            continue;
+        }
+        let Some(fee_destination_account) = fee_destination_account else {
+            burn_tokens(
+                source_account,
+                mint_account,
+                authority_account,
+                token_program,
+                fee_share,
+                signer_seeds,
+            )?;
+            burned_amount = burned_amount
+                .checked_add(fee_share)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+            continue;
+        };
+
+        let fee_transfer_instruction = token_instruction::transfer(
+            token_program.key,
+            source_account.key,
+            fee_destination_account.key,
+            authority_account.key,
+            &[],
+            fee_share,
+        )?;
+
+        invoke_signed(
+            &fee_transfer_instruction,
+            &[
+                source_account.clone(),
+                (*fee_destination_account).clone(),
+                authority_account.clone(),
+                token_program.clone(),
+            ],
+            signer_seeds,
+        )?;
+    }
+
+    // Transfer the remaining amount to the destination
+    let remaining_amount = amount.checked_sub(fee_amount).ok_or(ProgramError::ArithmeticOverflow)?;
     let transfer_instruction = token_instruction::transfer(
         token_program.key,
         source_account.key,
@@ -274,88 +2880,1269 @@ fn process_transfer(
         remaining_amount,
     )?;
 
-    invoke(
-        &transfer_instruction,
+    invoke_signed(
+        &transfer_instruction,
+        &[
+            source_account.clone(),
+            destination_account.clone(),
+            authority_account.clone(),
+            token_program.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    Ok((remaining_amount, burned_amount))
+}
+
+// Transfer `amount` of a native Token-2022 fee mint and return what the destination
+// received and what was burned. The extension withholds its base rate on every
+// transfer, the rest of the applicable rate is split between the fee destinations
+// as a surcharge.
+// `hook_accounts` must hold the transfer hook program and its extra accounts if
+// the mint has one.
+#[allow(clippy::too_many_arguments)]
+fn transfer_with_native_fee<'a>(
+    source_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    authority_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    fee_destinations: &[FeeDestination],
+    fee_destination_accounts: &[Option<&AccountInfo<'a>>],
+    hook_accounts: &[AccountInfo<'a>],
+    amount: u64,
+    fee_basis_points: u16,
+    epoch: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<(u64, u64), ProgramError> {
+    let mint_data = mint_account.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+    let native_fee_config = mint.get_extension::<NativeTransferFeeConfig>()?;
+    let decimals = mint.base.decimals;
+
+    let base_fee_basis_points: u16 = native_fee_config
+        .get_epoch_fee(epoch)
+        .transfer_fee_basis_points
+        .into();
+    let surcharge_amount =
+        calculate_fee(amount, fee_basis_points.saturating_sub(base_fee_basis_points))?;
+    let transfer_amount = amount
+        .checked_sub(surcharge_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    // Each share of the surcharge is its own transfer with its own withheld fee,
+    // burned shares have nothing withheld
+    let surcharge_shares = split_fee(surcharge_amount, fee_destinations)?
+        .into_iter()
+        .zip(fee_destinations)
+        .map(|(share, destination)| {
+            if destination.is_burn() {
+                return Ok((share, 0));
+            }
+            let withheld = native_fee_config
+                .calculate_epoch_fee(epoch, share)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+            Ok((share, withheld))
+        })
+        .collect::<Result<Vec<(u64, u64)>, ProgramError>>()?;
+    let surcharge_withheld: u64 = surcharge_shares.iter().map(|(_, withheld)| withheld).sum();
+    let transfer_withheld = native_fee_config
+        .calculate_epoch_fee(epoch, transfer_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    let transfer_hook_program_id = transfer_hook_extension::get_program_id(&mint);
+    drop(mint_data);
+
+    // Build a transfer to `destination`, adding the accounts the transfer hook needs
+    let transfer_to = |destination: &AccountInfo<'a>, amount: u64, fee: u64| -> ProgramResult {
+        let mut instruction = transfer_fee_instruction::transfer_checked_with_fee(
+            token_program.key,
+            source_account.key,
+            mint_account.key,
+            destination.key,
+            authority_account.key,
+            &[],
+            amount,
+            decimals,
+            fee,
+        )?;
+        let mut account_infos = vec![
+            source_account.clone(),
+            mint_account.clone(),
+            destination.clone(),
+            authority_account.clone(),
+        ];
+        if let Some(hook_program_id) = transfer_hook_program_id {
+            add_cpi_accounts_for_execute(
+                &mut instruction,
+                &mut account_infos,
+                mint_account.key,
+                &hook_program_id,
+                hook_accounts,
+            )?;
+        }
+        account_infos.push(token_program.clone());
+
+        invoke_signed(&instruction, &account_infos, signer_seeds)
+    };
+
+    msg!(
+        "Transfer fee: {} withheld, {} surcharge",
+        surcharge_withheld + transfer_withheld,
+        surcharge_amount
+    );
+
+    let mut burned_amount = 0u64;
+    for (fee_destination_account, (share, withheld)) in
+        fee_destination_accounts.iter().zip(surcharge_shares)
+    {
+        if share == 0 {
+            continue;
+        }
+        match fee_destination_account {
+            Some(fee_destination_account) => {
+                transfer_to(fee_destination_account, share, withheld)?;
+            }
+            None => {
+                burn_tokens(
+                    source_account,
+                    mint_account,
+                    authority_account,
+                    token_program,
+                    share,
+                    signer_seeds,
+                )?;
+                burned_amount = burned_amount
+                    .checked_add(share)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+            }
+        }
+    }
+    transfer_to(destination_account, transfer_amount, transfer_withheld)?;
+
+    let received_amount = transfer_amount
+        .checked_sub(transfer_withheld)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    Ok((received_amount, burned_amount))
+}
+
+// Move the fees withheld in `token_account` to the mint, where the account's
+// holder can no longer get them refunded. Anyone may harvest to the mint.
+fn harvest_to_mint<'a>(
+    token_program: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    token_account: &AccountInfo<'a>,
+) -> ProgramResult {
+    let harvest_instruction = transfer_fee_instruction::harvest_withheld_tokens_to_mint(
+        token_program.key,
+        mint_account.key,
+        &[token_account.key],
+    )?;
+
+    invoke(
+        &harvest_instruction,
+        &[mint_account.clone(), token_account.clone(), token_program.clone()],
+    )
+}
+
+// Release the fees withheld in `token_account` back into its balance, signed by
+// the fee config PDA as the extension's withdraw authority
+fn refund_withheld_fee<'a>(
+    program_id: &Pubkey,
+    token_program: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    config_account: &AccountInfo<'a>,
+    token_account: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (_, bump_seed) = find_fee_config_address(program_id, mint_account.key);
+    let config_signer_seeds: &[&[u8]] = &[FEE_CONFIG_SEED, mint_account.key.as_ref(), &[bump_seed]];
+
+    let refund_instruction = transfer_fee_instruction::withdraw_withheld_tokens_from_accounts(
+        token_program.key,
+        mint_account.key,
+        token_account.key,
+        config_account.key,
+        &[],
+        &[token_account.key],
+    )?;
+
+    invoke_signed(
+        &refund_instruction,
+        &[
+            mint_account.clone(),
+            token_account.clone(),
+            config_account.clone(),
+            token_program.clone(),
+        ],
+        &[config_signer_seeds],
+    )
+}
+
+fn process_update_holder_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    holder: Pubkey,
+    balance: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let holder_account = next_account_info(account_info_iter)?;
+
+    // Verify the rewards program and pool
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+    if holder_account.key != &holder {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Forward the update to the rewards program
+    let update_instruction = rewards::create_update_holder_balance_instruction(
+        rewards_program.key,
+        rewards_pool_account.key,
+        holder_account.key,
+        balance,
+    )?;
+
+    invoke(
+        &update_instruction,
+        &[
+            rewards_pool_account.clone(),
+            holder_account.clone(),
+            rewards_program.clone(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+// Verify the rewards program and pool accounts are the ones in the fee config
+fn check_rewards_accounts(
+    fee_config: &TransferFeeConfig,
+    rewards_program: &AccountInfo,
+    rewards_pool_account: &AccountInfo,
+) -> ProgramResult {
+    if rewards_program.key != &fee_config.rewards_program {
+        return Err(TokenError::InvalidRewardsProgram.into());
+    }
+    if rewards_pool_account.key != &fee_config.rewards_pool {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+// Record the current balance of `holder_account` in the rewards pool
+fn sync_holder_balance<'a>(
+    rewards_program: &AccountInfo<'a>,
+    rewards_pool_account: &AccountInfo<'a>,
+    holder_account: &AccountInfo<'a>,
+) -> ProgramResult {
+    let balance = StateWithExtensions::<Account>::unpack(&holder_account.data.borrow())?
+        .base
+        .amount;
+    let update_instruction = rewards::create_update_holder_balance_instruction(
+        rewards_program.key,
+        rewards_pool_account.key,
+        holder_account.key,
+        balance,
+    )?;
+
+    invoke(
+        &update_instruction,
+        &[
+            rewards_pool_account.clone(),
+            holder_account.clone(),
+            rewards_program.clone(),
+        ],
+    )
+}
+
+// Record `balance` locked in `position_account` for `holder_account` in the
+// rewards pool, earning with `weight_basis_points`, signing as the lock
+// authority PDA of the mint
+#[allow(clippy::too_many_arguments)]
+fn record_locked_balance<'a>(
+    program_id: &Pubkey,
+    mint_account: &AccountInfo<'a>,
+    lock_authority: &AccountInfo<'a>,
+    rewards_program: &AccountInfo<'a>,
+    rewards_pool_account: &AccountInfo<'a>,
+    position_account: &AccountInfo<'a>,
+    holder_account: &AccountInfo<'a>,
+    balance: u64,
+    weight_basis_points: u16,
+) -> ProgramResult {
+    let bump_seed = check_lock_authority(program_id, mint_account, lock_authority)?;
+    let update_instruction = rewards::create_update_locked_balance_instruction(
+        rewards_program.key,
+        rewards_pool_account.key,
+        lock_authority.key,
+        position_account.key,
+        holder_account.key,
+        balance,
+        weight_basis_points,
+    )?;
+
+    invoke_signed(
+        &update_instruction,
+        &[
+            rewards_pool_account.clone(),
+            lock_authority.clone(),
+            position_account.clone(),
+            holder_account.clone(),
+            rewards_program.clone(),
+        ],
+        &[&[LOCK_AUTHORITY_SEED, mint_account.key.as_ref(), &[bump_seed]]],
+    )
+}
+
+// Create a token account of the mint at the PDA `signer_seeds` sign for, held by
+// the lock authority PDA. It gets the account extensions the mint requires, such
+// as room for withheld fees.
+fn create_locked_token_account<'a>(
+    payer: &AccountInfo<'a>,
+    token_account: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    lock_authority: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let mint_extensions =
+        StateWithExtensions::<Mint>::unpack(&mint_account.data.borrow())?.get_extension_types()?;
+    let account_len = ExtensionType::try_calculate_account_len::<Account>(
+        &ExtensionType::get_required_init_account_extensions(&mint_extensions),
+    )?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            token_account.key,
+            Rent::get()?.minimum_balance(account_len),
+            account_len as u64,
+            token_program.key,
+        ),
+        &[
+            payer.clone(),
+            token_account.clone(),
+            system_program_account.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+    invoke(
+        &token_instruction::initialize_account3(
+            token_program.key,
+            token_account.key,
+            mint_account.key,
+            lock_authority.key,
+        )?,
+        &[
+            token_account.clone(),
+            mint_account.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+// Move `amount` out of a token account held by the lock authority PDA
+#[allow(clippy::too_many_arguments)]
+fn transfer_from_locked_account<'a>(
+    program_id: &Pubkey,
+    mint_account: &AccountInfo<'a>,
+    locked_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    lock_authority: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    hook_accounts: &[AccountInfo<'a>],
+    amount: u64,
+) -> ProgramResult {
+    let bump_seed = check_lock_authority(program_id, mint_account, lock_authority)?;
+    transfer_tokens(
+        locked_account,
+        mint_account,
+        destination_account,
+        lock_authority,
+        token_program,
+        hook_accounts,
+        amount,
+        &[&[LOCK_AUTHORITY_SEED, mint_account.key.as_ref(), &[bump_seed]]],
+    )
+}
+
+fn token_account_amount(token_account: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(StateWithExtensions::<Account>::unpack(&token_account.data.borrow())?
+        .base
+        .amount)
+}
+
+// Burn tokens and update the holder's recorded balance, and with it the total
+// eligible supply, in the rewards pool right away
+fn process_burn(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+
+    burn_tokens(
+        source_account,
+        mint_account,
+        authority_account,
+        token_program,
+        amount,
+        &[],
+    )?;
+
+    sync_holder_balance(rewards_program, rewards_pool_account, source_account)
+}
+
+fn process_set_transfer_fee(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    buy_fee_basis_points: u16,
+    sell_fee_basis_points: u16,
+    transfer_fee_basis_points: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let fee_authority = next_account_info(account_info_iter)?;
+    let token_program = if mint_account.owner != program_id {
+        Some(next_account_info(account_info_iter)?)
+    } else {
+        None
+    };
+
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+
+    // Only the fee authority may change the fee rates
+    check_fee_authority(program_id, &fee_config, fee_authority, account_info_iter.as_slice())?;
+
+    validate_fee_basis_points(
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    )?;
+
+    // Once the newer schedule is in effect it becomes the older one, otherwise
+    // the pending schedule is simply replaced
+    let epoch = Clock::get()?.epoch;
+    if epoch >= fee_config.newer_fee_schedule.epoch {
+        fee_config.older_fee_schedule = fee_config.newer_fee_schedule;
+    }
+    fee_config.newer_fee_schedule = FeeSchedule {
+        epoch: epoch
+            .checked_add(FEE_SCHEDULE_ACTIVATION_DELAY_EPOCHS)
+            .ok_or(ProgramError::ArithmeticOverflow)?,
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    };
+    msg!(
+        "Fee schedule scheduled for epoch {}",
+        fee_config.newer_fee_schedule.epoch
+    );
+    store_fee_config(mint_account, config_account, &fee_config)?;
+    events::Event::FeeConfigUpdated(events::FeeConfigUpdated {
+        mint: *mint_account.key,
+        epoch: fee_config.newer_fee_schedule.epoch,
+        buy_fee_basis_points,
+        sell_fee_basis_points,
+        transfer_fee_basis_points,
+    })
+    .emit();
+
+    // Keep the native extension's base rate in line. Token-2022 applies the
+    // same two epoch delay to the new rate.
+    if let Some(token_program) = token_program {
+        let maximum_fee: u64 = {
+            let mint_data = mint_account.data.borrow();
+            let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+            mint.get_extension::<NativeTransferFeeConfig>()?
+                .newer_transfer_fee
+                .maximum_fee
+                .into()
+        };
+        let (_, bump_seed) = find_fee_config_address(program_id, mint_account.key);
+
+        let set_fee_instruction = transfer_fee_instruction::set_transfer_fee(
+            token_program.key,
+            mint_account.key,
+            config_account.key,
+            &[],
+            fee_config.newer_fee_schedule.base_fee_basis_points(),
+            maximum_fee,
+        )?;
+
+        invoke_signed(
+            &set_fee_instruction,
+            &[
+                mint_account.clone(),
+                config_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[FEE_CONFIG_SEED, mint_account.key.as_ref(), &[bump_seed]]],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Sweep the fees withheld by the extension into the fee vault, a token account
+// of the mint owned by the fee config PDA, and split them between the fee
+// destinations like the surcharge. Expects the mint, the fee config PDA, the fee
+// vault, the token program, one account per fee destination, the transfer hook
+// program, its extra account metas PDA, the rewards program and the rewards
+// pool. Any remaining accounts are token accounts holding withheld fees.
+fn process_harvest_withheld_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let fee_vault_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    // Only native Token-2022 mints have withheld fees
+    if config_account.key == mint_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+    let fee_destination_accounts = next_fee_destination_accounts(&fee_config, account_info_iter)?;
+    let mut hook_accounts = vec![config_account.clone()];
+    for _ in 0..4 {
+        hook_accounts.push(next_account_info(account_info_iter)?.clone());
+    }
+    let source_accounts = account_info_iter.as_slice();
+
+    let fee_vault = StateWithExtensions::<Account>::unpack(&fee_vault_account.data.borrow())?.base;
+    if fee_vault.mint != *mint_account.key || fee_vault.owner != *config_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (_, bump_seed) = find_fee_config_address(program_id, mint_account.key);
+    let config_signer_seeds: &[&[u8]] = &[FEE_CONFIG_SEED, mint_account.key.as_ref(), &[bump_seed]];
+
+    // Fees already harvested to the mint
+    let withdraw_mint_instruction = transfer_fee_instruction::withdraw_withheld_tokens_from_mint(
+        token_program.key,
+        mint_account.key,
+        fee_vault_account.key,
+        config_account.key,
+        &[],
+    )?;
+
+    invoke_signed(
+        &withdraw_mint_instruction,
+        &[
+            mint_account.clone(),
+            fee_vault_account.clone(),
+            config_account.clone(),
+            token_program.clone(),
+        ],
+        &[config_signer_seeds],
+    )?;
+
+    // Transfers into exempt accounts that bypassed the program had the base fee
+    // withheld, it is refunded to them instead of collected
+    let (exempt_accounts, source_accounts): (Vec<_>, Vec<_>) = source_accounts
+        .iter()
+        .partition(|account| fee_config.exempt_accounts.contains(account.key));
+    for exempt_account in exempt_accounts {
+        refund_withheld_fee(
+            program_id,
+            token_program,
+            mint_account,
+            config_account,
+            exempt_account,
+        )?;
+    }
+
+    // Fees still withheld in the given token accounts
+    if !source_accounts.is_empty() {
+        let sources: Vec<&Pubkey> = source_accounts.iter().map(|account| account.key).collect();
+        let withdraw_accounts_instruction =
+            transfer_fee_instruction::withdraw_withheld_tokens_from_accounts(
+                token_program.key,
+                mint_account.key,
+                fee_vault_account.key,
+                config_account.key,
+                &[],
+                &sources,
+            )?;
+
+        let mut account_infos = vec![
+            mint_account.clone(),
+            fee_vault_account.clone(),
+            config_account.clone(),
+            token_program.clone(),
+        ];
+        account_infos.extend(source_accounts.into_iter().cloned());
+
+        invoke_signed(
+            &withdraw_accounts_instruction,
+            &account_infos,
+            &[config_signer_seeds],
+        )?;
+    }
+
+    // Split the vault like the surcharge: each share moves in its own transfer and
+    // burned shares are burned from the vault
+    let harvested_amount =
+        StateWithExtensions::<Account>::unpack(&fee_vault_account.data.borrow())?
+            .base
+            .amount;
+    let shares = split_fee(harvested_amount, &fee_config.fee_destinations)?;
+    let mut burned_amount = 0u64;
+    for (fee_destination_account, share) in fee_destination_accounts.iter().zip(shares) {
+        if share == 0 {
+            continue;
+        }
+        match fee_destination_account {
+            Some(fee_destination_account) => transfer_tokens(
+                fee_vault_account,
+                mint_account,
+                fee_destination_account,
+                config_account,
+                token_program,
+                &hook_accounts,
+                share,
+                &[config_signer_seeds],
+            )?,
+            None => {
+                burn_tokens(
+                    fee_vault_account,
+                    mint_account,
+                    config_account,
+                    token_program,
+                    share,
+                    &[config_signer_seeds],
+                )?;
+                burned_amount = burned_amount
+                    .checked_add(share)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+            }
+        }
+    }
+    msg!("Harvested {} in fees, {} burned", harvested_amount, burned_amount);
+
+    // Keep the running total of tokens burned from fees
+    if burned_amount > 0 {
+        fee_config.total_fees_burned = fee_config
+            .total_fees_burned
+            .checked_add(burned_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        store_fee_config(mint_account, config_account, &fee_config)?;
+    }
+
+    Ok(())
+}
+
+// Transfer `amount` of a native Token-2022 mint with `transfer_checked`, adding
+// the accounts the transfer hook needs from `hook_accounts`. `signer_seeds` sign
+// for a PDA authority.
+#[allow(clippy::too_many_arguments)]
+fn transfer_tokens<'a>(
+    source_account: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    authority_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    hook_accounts: &[AccountInfo<'a>],
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mint_data = mint_account.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+    let decimals = mint.base.decimals;
+    let transfer_hook_program_id = transfer_hook_extension::get_program_id(&mint);
+    drop(mint_data);
+
+    let mut instruction = token_instruction::transfer_checked(
+        token_program.key,
+        source_account.key,
+        mint_account.key,
+        destination_account.key,
+        authority_account.key,
+        &[],
+        amount,
+        decimals,
+    )?;
+    let mut account_infos = vec![
+        source_account.clone(),
+        mint_account.clone(),
+        destination_account.clone(),
+        authority_account.clone(),
+    ];
+    if let Some(hook_program_id) = transfer_hook_program_id {
+        add_cpi_accounts_for_execute(
+            &mut instruction,
+            &mut account_infos,
+            mint_account.key,
+            &hook_program_id,
+            hook_accounts,
+        )?;
+    }
+    account_infos.push(token_program.clone());
+
+    invoke_signed(&instruction, &account_infos, signer_seeds)
+}
+
+// Apply `update` to the fee config on behalf of the fee authority. Expects the
+// mint account, the fee config PDA for native mints and the fee authority.
+fn update_fee_config<F>(program_id: &Pubkey, accounts: &[AccountInfo], update: F) -> ProgramResult
+where
+    F: FnOnce(&mut TransferFeeConfig) -> ProgramResult,
+{
+    update_fee_config_as(program_id, accounts, check_fee_authority, update)
+}
+
+// Apply `update` to the fee config on behalf of the authority accepted by
+// `check_authority`, expecting the same accounts as `update_fee_config`. Any
+// remaining accounts are the signers of a multisig authority.
+fn update_fee_config_as<C, F>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    check_authority: C,
+    update: F,
+) -> ProgramResult
+where
+    C: FnOnce(&Pubkey, &TransferFeeConfig, &AccountInfo, &[AccountInfo]) -> ProgramResult,
+    F: FnOnce(&mut TransferFeeConfig) -> ProgramResult,
+{
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+    check_authority(program_id, &fee_config, authority_account, account_info_iter.as_slice())?;
+
+    update(&mut fee_config)?;
+    store_fee_config(mint_account, config_account, &fee_config)
+}
+
+// Add `key` to a bounded list of accounts in the fee config
+fn add_to_account_list(list: &mut Vec<Pubkey>, key: Pubkey, max_len: usize) -> ProgramResult {
+    if list.contains(&key) {
+        return Err(TokenError::AccountAlreadyListed.into());
+    }
+    if list.len() >= max_len {
+        return Err(TokenError::AccountListFull.into());
+    }
+    list.push(key);
+    Ok(())
+}
+
+// Remove `key` from a list of accounts in the fee config
+fn remove_from_account_list(list: &mut Vec<Pubkey>, key: &Pubkey) -> ProgramResult {
+    let index = list
+        .iter()
+        .position(|listed| listed == key)
+        .ok_or(TokenError::AccountNotListed)?;
+    list.remove(index);
+    Ok(())
+}
+
+fn process_add_pool(program_id: &Pubkey, accounts: &[AccountInfo], pool: Pubkey) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        add_to_account_list(&mut fee_config.amm_pools, pool, MAX_AMM_POOLS)
+    })
+}
+
+fn process_remove_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool: Pubkey,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        remove_from_account_list(&mut fee_config.amm_pools, &pool)
+    })
+}
+
+fn process_set_fee_destinations(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee_destinations: Vec<FeeDestination>,
+) -> ProgramResult {
+    validate_fee_destinations(&fee_destinations)?;
+    update_fee_config(program_id, accounts, |fee_config| {
+        fee_config.fee_destinations = fee_destinations;
+        Ok(())
+    })
+}
+
+fn process_set_transfer_limits(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_transaction_amount: Option<u64>,
+    max_wallet_balance: Option<u64>,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        fee_config.max_transaction_amount = max_transaction_amount;
+        fee_config.max_wallet_balance = max_wallet_balance;
+        Ok(())
+    })
+}
+
+fn process_set_vesting_rewards_eligibility(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    enabled: bool,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        fee_config.vesting_counts_for_rewards = enabled;
+        Ok(())
+    })
+}
+
+fn process_set_early_unstake_penalty(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    penalty_basis_points: Option<u16>,
+) -> ProgramResult {
+    if penalty_basis_points.is_some_and(|penalty_basis_points| penalty_basis_points > 10_000) {
+        return Err(TokenError::FeeTooHigh.into());
+    }
+    update_fee_config(program_id, accounts, |fee_config| {
+        fee_config.early_unstake_penalty_basis_points = penalty_basis_points;
+        Ok(())
+    })
+}
+
+fn process_enable_trading(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    launch_window_slots: u64,
+    launch_buy_fee_basis_points: u16,
+    launch_sell_fee_basis_points: u16,
+) -> ProgramResult {
+    if launch_buy_fee_basis_points > MAX_LAUNCH_FEE_BASIS_POINTS
+        || launch_sell_fee_basis_points > MAX_LAUNCH_FEE_BASIS_POINTS
+    {
+        msg!("Launch fee exceeds maximum of {} basis points", MAX_LAUNCH_FEE_BASIS_POINTS);
+        return Err(TokenError::FeeTooHigh.into());
+    }
+
+    let slot = Clock::get()?.slot;
+    update_fee_config(program_id, accounts, |fee_config| {
+        if fee_config.trading_enabled_slot.is_some() {
+            return Err(TokenError::TradingAlreadyEnabled.into());
+        }
+        fee_config.trading_enabled_slot = Some(slot);
+        fee_config.launch_window_slots = launch_window_slots;
+        fee_config.launch_buy_fee_basis_points = launch_buy_fee_basis_points;
+        fee_config.launch_sell_fee_basis_points = launch_sell_fee_basis_points;
+        msg!(
+            "Trading enabled at slot {}, launch window ends at slot {}",
+            slot,
+            slot.saturating_add(launch_window_slots)
+        );
+        Ok(())
+    })
+}
+
+fn process_set_sell_limits(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sell_cooldown_seconds: Option<u64>,
+    max_daily_sell_volume: Option<u64>,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        fee_config.sell_cooldown_seconds = sell_cooldown_seconds;
+        fee_config.max_daily_sell_volume = max_daily_sell_volume;
+        Ok(())
+    })
+}
+
+fn process_set_compliance_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    compliance_authority: Option<Pubkey>,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        fee_config.compliance_authority = compliance_authority;
+        Ok(())
+    })
+}
+
+// Freeze or thaw a token account of the mint through the program's freeze
+// authority PDA, on behalf of the compliance authority
+fn process_freeze_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    freeze: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let token_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let compliance_authority = next_account_info(account_info_iter)?;
+    let freeze_authority_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_compliance_authority(
+        program_id,
+        &fee_config,
+        compliance_authority,
+        account_info_iter.as_slice(),
+    )?;
+
+    let (freeze_authority_address, bump_seed) =
+        find_freeze_authority_address(program_id, mint_account.key);
+    if freeze_authority_account.key != &freeze_authority_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let instruction = if freeze {
+        token_instruction::freeze_account(
+            token_program.key,
+            token_account.key,
+            mint_account.key,
+            freeze_authority_account.key,
+            &[],
+        )?
+    } else {
+        token_instruction::thaw_account(
+            token_program.key,
+            token_account.key,
+            mint_account.key,
+            freeze_authority_account.key,
+            &[],
+        )?
+    };
+
+    invoke_signed(
+        &instruction,
         &[
-            source_account.clone(),
-            destination_account.clone(),
-            authority_account.clone(),
+            token_account.clone(),
+            mint_account.clone(),
+            freeze_authority_account.clone(),
             token_program.clone(),
         ],
-    )?;
+        &[&[FREEZE_AUTHORITY_SEED, mint_account.key.as_ref(), &[bump_seed]]],
+    )
+}
 
-    // Update holder balance in rewards program
-    let update_balance_instruction = create_update_holder_balance_instruction(
-        program_id,
-        destination_account.key,
-        remaining_amount,
+fn process_add_blocked_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    account: Pubkey,
+) -> ProgramResult {
+    update_fee_config_as(program_id, accounts, check_compliance_authority, |fee_config| {
+        add_to_account_list(&mut fee_config.blocked_accounts, account, MAX_BLOCKED_ACCOUNTS)
+    })
+}
+
+fn process_remove_blocked_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    account: Pubkey,
+) -> ProgramResult {
+    update_fee_config_as(program_id, accounts, check_compliance_authority, |fee_config| {
+        remove_from_account_list(&mut fee_config.blocked_accounts, &account)
+    })
+}
+
+// Current supply as recorded in the mint
+fn get_mint_supply(program_id: &Pubkey, mint_account: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(unpack_mint(program_id, mint_account)?.supply)
+}
+
+// Current mint authority, as recorded in the fee config for capped native mints
+// and in the mint otherwise
+fn get_mint_authority(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    mint_account: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    if fee_config.capped_mint_authority.is_some() {
+        return Ok(fee_config.capped_mint_authority);
+    }
+    Ok(unpack_mint(program_id, mint_account)?.mint_authority.into())
+}
+
+// Program-owned mints keep their fee config after the mint data, native mints
+// their extensions
+fn unpack_mint(program_id: &Pubkey, mint_account: &AccountInfo) -> Result<Mint, ProgramError> {
+    let mint_data = mint_account.data.borrow();
+    if mint_account.owner == program_id {
+        Mint::unpack(mint_data.get(..Mint::LEN).ok_or(ProgramError::InvalidAccountData)?)
+    } else {
+        Ok(StateWithExtensions::<Mint>::unpack(&mint_data)?.base)
+    }
+}
+
+// Hand the mint authority of a native mint from `authority_account` to
+// `new_authority`, or drop it for good when there is none
+fn set_native_mint_authority<'a>(
+    mint_account: &AccountInfo<'a>,
+    authority_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    new_authority: Option<&Pubkey>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let set_authority_instruction = token_instruction::set_authority(
+        token_program.key,
+        mint_account.key,
+        new_authority,
+        token_instruction::AuthorityType::MintTokens,
+        authority_account.key,
+        &[],
     )?;
 
-    invoke(
-        &update_balance_instruction,
+    invoke_signed(
+        &set_authority_instruction,
         &[
             mint_account.clone(),
-            destination_account.clone(),
-            fee_config.rewards_program,
+            authority_account.clone(),
+            token_program.clone(),
         ],
-    )?;
+        signer_seeds,
+    )
+}
 
-    Ok(())
+// Propose a new mint or fee authority, or cancel the pending proposal. Expects
+// the mint account, the fee config PDA for native mints and the current
+// authority. Nothing changes hands until the proposed authority accepts. Any
+// remaining accounts are the signers of a multisig authority.
+fn process_propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    authority_type: AuthorityType,
+    new_authority: Option<Pubkey>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let signer_accounts = account_info_iter.as_slice();
+
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+
+    match authority_type {
+        AuthorityType::FeeConfig => {
+            check_fee_authority(program_id, &fee_config, authority_account, signer_accounts)?;
+            fee_config.pending_fee_authority = new_authority;
+        }
+        AuthorityType::Mint => {
+            let mint_authority = get_mint_authority(program_id, &fee_config, mint_account)?
+                .ok_or(ProgramError::InvalidAccountData)?;
+            multisig::check_authority(
+                program_id,
+                &mint_authority,
+                authority_account,
+                signer_accounts,
+            )?;
+            fee_config.pending_mint_authority = new_authority;
+        }
+    }
+
+    match new_authority {
+        Some(new_authority) => msg!("Proposed {:?} authority {}", authority_type, new_authority),
+        None => msg!("Cancelled {:?} authority proposal", authority_type),
+    }
+    store_fee_config(mint_account, config_account, &fee_config)
 }
 
-fn process_update_holder_balance(
+// Take over a proposed mint or fee authority. Expects the mint account, the fee
+// config PDA for native mints and the proposed authority. For the mint authority
+// of native mints the current mint authority follows, co-signing the Token-2022
+// hand over, and then the token program. Any remaining accounts are the signers
+// of multisig authorities.
+fn process_accept_authority(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    holder: Pubkey,
-    balance: u64,
+    authority_type: AuthorityType,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let mint_account = next_account_info(account_info_iter)?;
-    let rewards_program = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let new_authority_account = next_account_info(account_info_iter)?;
 
-    // Verify the rewards program
-    let mint_data = mint_account.data.borrow();
-    let mint = Mint::deserialize(&mint_data)?;
-    let fee_config: TransferFeeConfig = bincode::deserialize(
-        &mint_data[mint.serialized_len()..mint.serialized_len() + std::mem::size_of::<TransferFeeConfig>()],
-    ).map_err(|_| ProgramError::InvalidInstructionData)?;
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
 
-    if rewards_program.key != &fee_config.rewards_program {
-        return Err(ProgramError::IncorrectProgramId);
+    // The mint authority PDA holds the Token-2022 mint authority of capped mints,
+    // so only other native mints hand it over
+    let native_mint_accounts = if authority_type == AuthorityType::Mint
+        && mint_account.owner != program_id
+        && fee_config.capped_mint_authority.is_none()
+    {
+        let authority_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        Some((authority_account, token_program))
+    } else {
+        None
+    };
+    let signer_accounts = account_info_iter.as_slice();
+
+    match authority_type {
+        AuthorityType::FeeConfig => {
+            let pending_authority = fee_config
+                .pending_fee_authority
+                .ok_or(TokenError::NoPendingAuthority)?;
+            multisig::check_authority(
+                program_id,
+                &pending_authority,
+                new_authority_account,
+                signer_accounts,
+            )?;
+            fee_config.fee_authority = pending_authority;
+            fee_config.pending_fee_authority = None;
+        }
+        AuthorityType::Mint => {
+            let pending_authority = fee_config
+                .pending_mint_authority
+                .ok_or(TokenError::NoPendingAuthority)?;
+            multisig::check_authority(
+                program_id,
+                &pending_authority,
+                new_authority_account,
+                signer_accounts,
+            )?;
+
+            match native_mint_accounts {
+                Some((authority_account, token_program)) => {
+                    let mint_authority = get_mint_authority(program_id, &fee_config, mint_account)?
+                        .ok_or(ProgramError::InvalidAccountData)?;
+                    let multisig = multisig::check_authority(
+                        program_id,
+                        &mint_authority,
+                        authority_account,
+                        signer_accounts,
+                    )?;
+                    let multisig_seeds = multisig.as_ref().map(multisig::Multisig::signer_seeds);
+                    let signer_seeds: Vec<&[&[u8]]> =
+                        multisig_seeds.iter().map(|seeds| &seeds[..]).collect();
+                    set_native_mint_authority(
+                        mint_account,
+                        authority_account,
+                        token_program,
+                        Some(&pending_authority),
+                        &signer_seeds,
+                    )?;
+                }
+                None if fee_config.capped_mint_authority.is_some() => {
+                    fee_config.capped_mint_authority = Some(pending_authority);
+                }
+                None => {
+                    let mut mint_data = mint_account.data.borrow_mut();
+                    let mint_data = mint_data
+                        .get_mut(..Mint::LEN)
+                        .ok_or(ProgramError::InvalidAccountData)?;
+                    let mut mint = Mint::unpack(mint_data)?;
+                    mint.mint_authority = Some(pending_authority).into();
+                    Mint::pack(mint, mint_data)?;
+                }
+            }
+            fee_config.pending_mint_authority = None;
+        }
     }
 
-    // Forward the update to the rewards program
-    let update_instruction = rewards::create_update_holder_balance_instruction(
-        holder,
-        balance,
-    )?;
+    msg!("{:?} authority is now {}", authority_type, new_authority_account.key);
+    store_fee_config(mint_account, config_account, &fee_config)
+}
 
-    invoke(
-        &update_instruction,
-        &[
-            mint_account.clone(),
-            rewards_program.clone(),
-        ],
-    )?;
+// Drop the mint authority for good so the supply can never grow again. Expects
+// the mint account, the fee config PDA for native mints, the mint authority, for
+// native mints the token program, and for capped native mints the mint authority
+// PDA. Any remaining accounts are the signers of a multisig mint authority.
+fn process_finalize_supply(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let token_program = if mint_account.owner != program_id {
+        Some(next_account_info(account_info_iter)?)
+    } else {
+        None
+    };
+
+    let mut fee_config = load_fee_config(mint_account, config_account)?;
+    let mint_authority = get_mint_authority(program_id, &fee_config, mint_account)?
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let mint_authority_pda = match token_program {
+        Some(_) if fee_config.capped_mint_authority.is_some() => {
+            let mint_authority_pda = next_account_info(account_info_iter)?;
+            let (mint_authority_address, bump_seed) =
+                find_mint_authority_address(program_id, mint_account.key);
+            if mint_authority_pda.key != &mint_authority_address {
+                return Err(ProgramError::InvalidSeeds);
+            }
+            Some((mint_authority_pda, bump_seed))
+        }
+        _ => None,
+    };
+    let signer_accounts = account_info_iter.as_slice();
+    let multisig =
+        multisig::check_authority(program_id, &mint_authority, authority_account, signer_accounts)?;
+
+    match (token_program, mint_authority_pda) {
+        // The mint authority PDA of capped mints gives up the Token-2022 mint authority
+        (Some(token_program), Some((mint_authority_pda, bump_seed))) => {
+            set_native_mint_authority(
+                mint_account,
+                mint_authority_pda,
+                token_program,
+                None,
+                &[&[MINT_AUTHORITY_SEED, mint_account.key.as_ref(), &[bump_seed]]],
+            )?;
+            fee_config.capped_mint_authority = None;
+        }
+        (Some(token_program), None) => {
+            let multisig_seeds = multisig.as_ref().map(multisig::Multisig::signer_seeds);
+            let signer_seeds: Vec<&[&[u8]]> =
+                multisig_seeds.iter().map(|seeds| &seeds[..]).collect();
+            set_native_mint_authority(
+                mint_account,
+                authority_account,
+                token_program,
+                None,
+                &signer_seeds,
+            )?;
+        }
+        (None, _) => {
+            let mut mint_data = mint_account.data.borrow_mut();
+            let mint_data = mint_data
+                .get_mut(..Mint::LEN)
+                .ok_or(ProgramError::InvalidAccountData)?;
+            let mut mint = Mint::unpack(mint_data)?;
+            mint.mint_authority = None.into();
+            Mint::pack(mint, mint_data)?;
+        }
+    }
+
+    // Nobody can take over a mint authority that no longer exists
+    fee_config.pending_mint_authority = None;
+    store_fee_config(mint_account, config_account, &fee_config)?;
 
+    msg!("Supply finalized at {}", get_mint_supply(program_id, mint_account)?);
     Ok(())
 }
 
-// Helper function to create update holder balance instruction
-fn create_update_holder_balance_instruction(
+fn process_add_exempt_account(
     program_id: &Pubkey,
-    holder: &Pubkey,
-    balance: u64,
-) -> Result<solana_program::instruction::Instruction, ProgramError> {
-    let mut data = Vec::new();
-    data.push(3); // UpdateHolderBalance instruction tag
-    data.extend_from_slice(holder.as_ref());
-    data.extend_from_slice(&balance.to_le_bytes());
-
-    Ok(solana_program::instruction::Instruction {
-        program_id: *program_id,
-        accounts: vec![],
-        data,
+    accounts: &[AccountInfo],
+    account: Pubkey,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        add_to_account_list(&mut fee_config.exempt_accounts, account, MAX_EXEMPT_ACCOUNTS)
+    })
+}
+
+fn process_remove_exempt_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    account: Pubkey,
+) -> ProgramResult {
+    update_fee_config(program_id, accounts, |fee_config| {
+        remove_from_account_list(&mut fee_config.exempt_accounts, &account)
     })
 }
 
@@ -364,8 +4151,433 @@ mod tests {
     use super::*;
     use solana_program::clock::Epoch;
 
+    fn test_fee_config() -> TransferFeeConfig {
+        TransferFeeConfig::new(
+            FeeSchedule {
+                epoch: 0,
+                buy_fee_basis_points: 500,
+                sell_fee_basis_points: 500,
+                transfer_fee_basis_points: 0,
+            },
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        )
+    }
+
     #[test]
     fn test_sanity() {
         // Add tests here
     }
+
+    #[test]
+    fn test_unpack_set_transfer_fee() {
+        let mut data = vec![4];
+        data.extend_from_slice(&300u16.to_le_bytes());
+        data.extend_from_slice(&700u16.to_le_bytes());
+        data.extend_from_slice(&100u16.to_le_bytes());
+        assert!(matches!(
+            TokenInstruction::unpack(&data).unwrap(),
+            TokenInstruction::SetTransferFee {
+                buy_fee_basis_points: 300,
+                sell_fee_basis_points: 700,
+                transfer_fee_basis_points: 100,
+            }
+        ));
+        assert!(TokenInstruction::unpack(&data[..5]).is_err());
+    }
+
+    #[test]
+    fn test_unpack_transfer() {
+        let mut data = vec![2];
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        assert!(matches!(
+            TokenInstruction::unpack(&data).unwrap(),
+            TokenInstruction::Transfer {
+                amount: 1_000,
+                idle_sell_records: 0,
+                create_destination: false,
+            }
+        ));
+
+        // The create flag follows the idle sell record count
+        data.extend_from_slice(&[2, 1]);
+        assert!(matches!(
+            TokenInstruction::unpack(&data).unwrap(),
+            TokenInstruction::Transfer {
+                amount: 1_000,
+                idle_sell_records: 2,
+                create_destination: true,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_get_epoch_fee_schedule() {
+        let mut fee_config = test_fee_config();
+        fee_config.newer_fee_schedule = FeeSchedule {
+            epoch: 10,
+            buy_fee_basis_points: 200,
+            sell_fee_basis_points: 800,
+            transfer_fee_basis_points: 100,
+        };
+
+        let (name, schedule) = fee_config.get_epoch_fee_schedule(9);
+        assert_eq!(name, "older");
+        assert_eq!(schedule.fee_basis_points(TransferDirection::Sell), 500);
+
+        let (name, schedule) = fee_config.get_epoch_fee_schedule(10);
+        assert_eq!(name, "newer");
+        assert_eq!(schedule.fee_basis_points(TransferDirection::Buy), 200);
+        assert_eq!(schedule.fee_basis_points(TransferDirection::Sell), 800);
+        assert_eq!(schedule.fee_basis_points(TransferDirection::Transfer), 100);
+        assert_eq!(schedule.base_fee_basis_points(), 100);
+    }
+
+    #[test]
+    fn test_classify_transfer() {
+        let pool = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let other_wallet = Pubkey::new_unique();
+        let mut fee_config = test_fee_config();
+        fee_config.amm_pools = vec![pool];
+        fee_config.exempt_accounts = vec![wallet];
+
+        assert_eq!(fee_config.classify_transfer(&pool, &wallet), TransferDirection::Buy);
+        assert_eq!(fee_config.classify_transfer(&wallet, &pool), TransferDirection::Sell);
+        assert_eq!(
+            fee_config.classify_transfer(&wallet, &other_wallet),
+            TransferDirection::Transfer
+        );
+        assert!(fee_config.is_fee_exempt(&pool, &wallet));
+        assert!(!fee_config.is_fee_exempt(&pool, &other_wallet));
+    }
+
+    #[test]
+    fn test_transfer_limits() {
+        let mut fee_config = test_fee_config();
+        assert!(fee_config.check_transaction_amount(TransferDirection::Buy, u64::MAX).is_ok());
+        assert!(fee_config.check_wallet_balance(TransferDirection::Buy, u64::MAX).is_ok());
+
+        fee_config.max_transaction_amount = Some(1_000);
+        fee_config.max_wallet_balance = Some(5_000);
+        assert!(fee_config.check_transaction_amount(TransferDirection::Sell, 1_000).is_ok());
+        assert_eq!(
+            fee_config.check_transaction_amount(TransferDirection::Sell, 1_001),
+            Err(TokenError::MaxTransactionAmountExceeded.into())
+        );
+        assert!(fee_config.check_transaction_amount(TransferDirection::Transfer, 1_001).is_ok());
+        assert_eq!(
+            fee_config.check_wallet_balance(TransferDirection::Buy, 5_001),
+            Err(TokenError::MaxWalletBalanceExceeded.into())
+        );
+        assert!(fee_config.check_wallet_balance(TransferDirection::Sell, 5_001).is_ok());
+    }
+
+    #[test]
+    fn test_launch_controls() {
+        let mut fee_config = test_fee_config();
+        assert_eq!(
+            fee_config.check_trading_enabled(),
+            Err(TokenError::TradingNotEnabled.into())
+        );
+        assert_eq!(fee_config.launch_fee_basis_points(TransferDirection::Buy, 0), None);
+
+        fee_config.trading_enabled_slot = Some(100);
+        fee_config.launch_window_slots = 10;
+        fee_config.launch_buy_fee_basis_points = 2_000;
+        fee_config.launch_sell_fee_basis_points = 3_000;
+        assert!(fee_config.check_trading_enabled().is_ok());
+        assert_eq!(
+            fee_config.launch_fee_basis_points(TransferDirection::Buy, 100),
+            Some(2_000)
+        );
+        assert_eq!(
+            fee_config.launch_fee_basis_points(TransferDirection::Sell, 109),
+            Some(3_000)
+        );
+        assert_eq!(fee_config.launch_fee_basis_points(TransferDirection::Sell, 110), None);
+        assert_eq!(
+            fee_config.launch_fee_basis_points(TransferDirection::Transfer, 100),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sell_record() {
+        let mut fee_config = test_fee_config();
+        fee_config.sell_cooldown_seconds = Some(60);
+        fee_config.max_daily_sell_volume = Some(1_000);
+        let mut sell_record = SellRecord::default();
+        let day = 24 * SECONDS_PER_HOUR;
+
+        let now = 10 * day;
+        assert!(sell_record.check_sell(&fee_config, now, 600).is_ok());
+        sell_record.record_sell(now, 600).unwrap();
+        assert_eq!(
+            sell_record.check_sell(&fee_config, now + 59, 100),
+            Err(TokenError::SellCooldownActive.into())
+        );
+        assert_eq!(
+            sell_record.check_sell(&fee_config, now + 60, 401),
+            Err(TokenError::DailySellVolumeExceeded.into())
+        );
+
+        let later = now + 5 * SECONDS_PER_HOUR;
+        sell_record.record_sell(later, 400).unwrap();
+        assert_eq!(sell_record.sold_volume(later), 1_000);
+        assert!(!sell_record.is_idle(&fee_config, later + 60));
+
+        // The first sell leaves the window a day after it was made
+        assert_eq!(sell_record.sold_volume(now + day), 400);
+        assert!(sell_record.check_sell(&fee_config, now + day, 600).is_ok());
+        assert_eq!(sell_record.sold_volume(later + day), 0);
+        assert!(sell_record.is_idle(&fee_config, later + day));
+
+        sell_record.record_sell(later + day, 100).unwrap();
+        assert_eq!(sell_record.sold_volume(later + day), 100);
+    }
+
+    #[test]
+    fn test_check_not_blocked() {
+        let blocked = Pubkey::new_unique();
+        let mut fee_config = test_fee_config();
+        assert!(fee_config.check_not_blocked(&[&blocked]).is_ok());
+
+        fee_config.blocked_accounts = vec![blocked];
+        assert_eq!(
+            fee_config.check_not_blocked(&[&Pubkey::new_unique(), &blocked]),
+            Err(TokenError::AccountBlocked.into())
+        );
+        assert!(fee_config.check_not_blocked(&[&Pubkey::new_unique()]).is_ok());
+    }
+
+    #[test]
+    fn test_unpack_authority_instructions() {
+        let new_authority = Pubkey::new_unique();
+        let mut data = vec![20, 1];
+        data.extend_from_slice(new_authority.as_ref());
+        assert!(matches!(
+            TokenInstruction::unpack(&data).unwrap(),
+            TokenInstruction::ProposeAuthority {
+                authority_type: AuthorityType::FeeConfig,
+                new_authority: Some(key),
+            } if key == new_authority
+        ));
+
+        let mut data = vec![20, 0];
+        data.extend_from_slice(&[0; 32]);
+        assert!(matches!(
+            TokenInstruction::unpack(&data).unwrap(),
+            TokenInstruction::ProposeAuthority {
+                authority_type: AuthorityType::Mint,
+                new_authority: None,
+            }
+        ));
+
+        assert!(matches!(
+            TokenInstruction::unpack(&[21, 0]).unwrap(),
+            TokenInstruction::AcceptAuthority {
+                authority_type: AuthorityType::Mint,
+            }
+        ));
+        assert!(TokenInstruction::unpack(&[21, 2]).is_err());
+    }
+
+    #[test]
+    fn test_check_max_supply() {
+        let mut fee_config = test_fee_config();
+        assert!(fee_config.check_max_supply(u64::MAX - 1, 1).is_ok());
+        assert_eq!(
+            fee_config.check_max_supply(u64::MAX, 1),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+
+        fee_config.max_supply = Some(1_000);
+        assert!(fee_config.check_max_supply(400, 600).is_ok());
+        assert_eq!(
+            fee_config.check_max_supply(400, 601),
+            Err(TokenError::MaxSupplyExceeded.into())
+        );
+    }
+
+    #[test]
+    fn test_unpack_max_supply() {
+        assert_eq!(unpack_max_supply(&[]), Ok((None, &[][..])));
+        assert_eq!(unpack_max_supply(&[1]), Ok((None, &[][..])));
+        assert_eq!(unpack_max_supply(&[1, 0, 7]), Ok((None, &[7][..])));
+
+        let mut data = vec![0, 1];
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        assert_eq!(unpack_max_supply(&data), Ok((Some(1_000), &[][..])));
+        assert!(unpack_max_supply(&data[..9]).is_err());
+        assert!(unpack_max_supply(&[0, 2]).is_err());
+    }
+
+    #[test]
+    fn test_unpack_metadata() {
+        let pack_string = |data: &mut Vec<u8>, value: &str| {
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(value.as_bytes());
+        };
+        let mut data = Vec::new();
+        pack_string(&mut data, "Token");
+        pack_string(&mut data, "TKN");
+        pack_string(&mut data, "https://example.com/token.json");
+        data.push(1);
+        pack_string(&mut data, "website");
+        pack_string(&mut data, "https://example.com");
+
+        let metadata = MintMetadata {
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            uri: "https://example.com/token.json".to_string(),
+            additional_metadata: vec![("website".to_string(), "https://example.com".to_string())],
+        };
+        assert_eq!(MintMetadata::unpack(&data).unwrap(), (metadata.clone(), &[][..]));
+        assert!(MintMetadata::unpack(&data[..data.len() - 1]).is_err());
+
+        // Each optional field of the initialize instructions has its own presence byte
+        let mut init_data = vec![5, 6];
+        init_data.extend_from_slice(Pubkey::new_unique().as_ref());
+        init_data.extend_from_slice(&[0; 2 * 3 + 8]);
+        init_data.extend_from_slice(&[1, 0, 1]);
+        init_data.extend_from_slice(&data);
+        assert!(matches!(
+            TokenInstruction::unpack(&init_data).unwrap(),
+            TokenInstruction::InitializeNativeFeeMint {
+                freeze_authority_enabled: true,
+                max_supply: None,
+                metadata: Some(unpacked),
+                ..
+            } if unpacked == metadata
+        ));
+
+        // Program-owned mints reject metadata
+        let mut init_data = vec![0, 6];
+        init_data.extend_from_slice(&[0; 32 + 2 * 3]);
+        init_data.extend_from_slice(&[0, 1]);
+        init_data.extend_from_slice(&1_000u64.to_le_bytes());
+        assert!(matches!(
+            TokenInstruction::unpack(&init_data).unwrap(),
+            TokenInstruction::InitializeMint {
+                max_supply: Some(1_000),
+                ..
+            }
+        ));
+        init_data.push(1);
+        init_data.extend_from_slice(&data);
+        assert_eq!(
+            TokenInstruction::unpack(&init_data).unwrap_err(),
+            TokenError::MetadataNotSupported.into()
+        );
+
+        let mut data = vec![25, 3];
+        pack_string(&mut data, "website");
+        pack_string(&mut data, "https://example.org");
+        assert!(matches!(
+            TokenInstruction::unpack(&data).unwrap(),
+            TokenInstruction::UpdateMetadata {
+                field: Field::Key(key),
+                value,
+            } if key == "website" && value == "https://example.org"
+        ));
+    }
+
+    #[test]
+    fn test_unpack_batch_transfer() {
+        let recipients = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut data = vec![27, 2];
+        data.extend_from_slice(recipients[0].as_ref());
+        data.extend_from_slice(&100u64.to_le_bytes());
+        data.extend_from_slice(recipients[1].as_ref());
+        data.extend_from_slice(&250u64.to_le_bytes());
+        match TokenInstruction::unpack(&data).unwrap() {
+            TokenInstruction::BatchTransfer { transfers } => {
+                assert_eq!(transfers, vec![(recipients[0], 100), (recipients[1], 250)]);
+            }
+            instruction => panic!("unexpected instruction {:?}", instruction),
+        }
+
+        // A missing amount fails
+        assert!(TokenInstruction::unpack(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_account_list() {
+        let key = Pubkey::new_unique();
+        let mut list = Vec::new();
+        add_to_account_list(&mut list, key, 1).unwrap();
+        assert_eq!(
+            add_to_account_list(&mut list, key, 2),
+            Err(TokenError::AccountAlreadyListed.into())
+        );
+        assert_eq!(
+            add_to_account_list(&mut list, Pubkey::new_unique(), 1),
+            Err(TokenError::AccountListFull.into())
+        );
+        remove_from_account_list(&mut list, &key).unwrap();
+        assert!(list.is_empty());
+        assert_eq!(
+            remove_from_account_list(&mut list, &key),
+            Err(TokenError::AccountNotListed.into())
+        );
+    }
+
+    #[test]
+    fn test_split_fee() {
+        let fee_destinations = [
+            FeeDestination {
+                account: Pubkey::new_unique(),
+                weight_basis_points: 3_333,
+            },
+            FeeDestination {
+                account: Pubkey::new_unique(),
+                weight_basis_points: 3_333,
+            },
+            FeeDestination {
+                account: Pubkey::new_unique(),
+                weight_basis_points: 3_334,
+            },
+        ];
+        assert!(validate_fee_destinations(&fee_destinations).is_ok());
+        assert_eq!(split_fee(100, &fee_destinations).unwrap(), vec![34, 33, 33]);
+        assert_eq!(split_fee(0, &fee_destinations).unwrap(), vec![0, 0, 0]);
+
+        assert_eq!(
+            validate_fee_destinations(&fee_destinations[..2]),
+            Err(TokenError::InvalidFeeDestinations.into())
+        );
+        assert_eq!(
+            validate_fee_destinations(&[]),
+            Err(TokenError::InvalidFeeDestinations.into())
+        );
+        assert!(!fee_destinations[0].is_burn());
+        assert!(FeeDestination {
+            account: BURN_FEE_DESTINATION,
+            weight_basis_points: 10_000,
+        }
+        .is_burn());
+    }
+
+    #[test]
+    fn test_calculate_fee() {
+        assert_eq!(calculate_fee(1_000, 500).unwrap(), 50);
+        assert_eq!(calculate_fee(19, 500).unwrap(), 0);
+        assert_eq!(calculate_fee(u64::MAX, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_validate_fee_basis_points() {
+        assert!(validate_fee_basis_points(0, MAX_FEE_BASIS_POINTS, 0).is_ok());
+        assert_eq!(
+            validate_fee_basis_points(MAX_FEE_BASIS_POINTS + 1, 500, 0),
+            Err(TokenError::FeeTooHigh.into())
+        );
+        assert_eq!(
+            validate_fee_basis_points(500, 500, MAX_FEE_BASIS_POINTS + 1),
+            Err(TokenError::FeeTooHigh.into())
+        );
+    }
 } 
\ No newline at end of file
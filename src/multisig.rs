@@ -0,0 +1,212 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    system_program,
+    sysvar::Sysvar,
+};
+use serde::{Deserialize, Serialize};
+
+// Most keys a multisig can have
+pub const MAX_SIGNERS: usize = 11;
+
+// Seed of multisig PDAs, together with the key the multisig was created with.
+// Being a PDA lets the program sign CPIs as the multisig once enough of its
+// signers have signed.
+pub const MULTISIG_SEED: &[u8] = b"multisig";
+
+// Size of a bincode serialized Multisig
+pub const MULTISIG_ACCOUNT_LEN: usize = 1 + 1 + 32 + 1 + 32 * MAX_SIGNERS;
+
+// M-of-N multisig accepted wherever the owning program checks an authority
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Multisig {
+    threshold: u8, // Signatures required, M
+    signer_count: u8, // Valid keys in `signers`, N
+    create_key: Pubkey,
+    bump_seed: u8,
+    signers: [Pubkey; MAX_SIGNERS],
+}
+
+impl Multisig {
+    fn new(
+        threshold: u8,
+        create_key: Pubkey,
+        bump_seed: u8,
+        signer_keys: &[Pubkey],
+    ) -> Result<Self, ProgramError> {
+        if threshold == 0
+            || threshold as usize > signer_keys.len()
+            || signer_keys.len() > MAX_SIGNERS
+        {
+            msg!("Invalid multisig: {} of {} signers", threshold, signer_keys.len());
+            return Err(ProgramError::InvalidArgument);
+        }
+        // A key listed twice would count once, leaving fewer than N keys able to sign
+        for (index, signer_key) in signer_keys.iter().enumerate() {
+            if signer_keys[..index].contains(signer_key) {
+                msg!("Duplicate multisig signer {}", signer_key);
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        let mut signers = [Pubkey::default(); MAX_SIGNERS];
+        signers[..signer_keys.len()].copy_from_slice(signer_keys);
+        Ok(Self {
+            threshold,
+            signer_count: signer_keys.len() as u8,
+            create_key,
+            bump_seed,
+            signers,
+        })
+    }
+
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let multisig: Self =
+            bincode::deserialize(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if multisig.threshold == 0 {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(multisig)
+    }
+
+    // Number of distinct multisig keys among `signer_keys`
+    fn count_signers<'a, I: Iterator<Item = &'a Pubkey>>(&self, signer_keys: I) -> usize {
+        let mut signed = [false; MAX_SIGNERS];
+        for signer_key in signer_keys {
+            if let Some(position) = self.signers[..self.signer_count as usize]
+                .iter()
+                .position(|key| key == signer_key)
+            {
+                signed[position] = true;
+            }
+        }
+        signed.iter().filter(|&&signed| signed).count()
+    }
+
+    // Seeds to sign CPIs as the multisig
+    pub fn signer_seeds(&self) -> [&[u8]; 3] {
+        [
+            MULTISIG_SEED,
+            self.create_key.as_ref(),
+            std::slice::from_ref(&self.bump_seed),
+        ]
+    }
+}
+
+pub fn find_multisig_address(program_id: &Pubkey, create_key: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MULTISIG_SEED, create_key.as_ref()], program_id)
+}
+
+// Check that `authority_account` is `authority` and has signed. A multisig of
+// `program_id` signs through at least `threshold` of its keys among
+// `signer_accounts`, and is returned so the caller can sign CPIs as it.
+pub fn check_authority(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    authority_account: &AccountInfo,
+    signer_accounts: &[AccountInfo],
+) -> Result<Option<Multisig>, ProgramError> {
+    if authority_account.owner == program_id {
+        if authority_account.key != authority {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let multisig = Multisig::unpack(&authority_account.data.borrow())?;
+        let signed = multisig.count_signers(
+            signer_accounts
+                .iter()
+                .filter(|account| account.is_signer)
+                .map(|account| account.key),
+        );
+        if signed < multisig.threshold as usize {
+            msg!("Multisig has {} of {} required signatures", signed, multisig.threshold);
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        return Ok(Some(multisig));
+    }
+
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if authority_account.key != authority {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(None)
+}
+
+// Create an M-of-N multisig PDA. Expects the multisig PDA, the key it is derived
+// from, the payer, the system program and then the N signer keys.
+pub fn process_initialize_multisig(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    threshold: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let multisig_account = next_account_info(account_info_iter)?;
+    let create_key = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let signer_keys: Vec<Pubkey> = account_info_iter.map(|account| *account.key).collect();
+
+    if system_program_account.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (multisig_address, bump_seed) = find_multisig_address(program_id, create_key.key);
+    if multisig_account.key != &multisig_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let multisig = Multisig::new(threshold, *create_key.key, bump_seed, &signer_keys)?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            multisig_account.key,
+            Rent::get()?.minimum_balance(MULTISIG_ACCOUNT_LEN),
+            MULTISIG_ACCOUNT_LEN as u64,
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            multisig_account.clone(),
+            system_program_account.clone(),
+        ],
+        &[&multisig.signer_seeds()],
+    )?;
+
+    bincode::serialize_into(&mut &mut multisig_account.data.borrow_mut()[..], &multisig)
+        .map_err(|_| ProgramError::AccountDataTooSmall)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_signers() {
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let multisig = Multisig::new(2, Pubkey::new_unique(), 255, &keys).unwrap();
+
+        assert_eq!(multisig.count_signers([&keys[0]].into_iter()), 1);
+        assert_eq!(multisig.count_signers([&keys[0], &keys[0]].into_iter()), 1);
+        assert_eq!(multisig.count_signers([&keys[2], &keys[0]].into_iter()), 2);
+        assert_eq!(multisig.count_signers([&Pubkey::default()].into_iter()), 0);
+
+        assert_eq!(
+            Multisig::new(4, Pubkey::new_unique(), 255, &keys),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            Multisig::new(0, Pubkey::new_unique(), 255, &keys),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            Multisig::new(2, Pubkey::new_unique(), 255, &[keys[0], keys[1], keys[0]]),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+}
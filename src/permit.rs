@@ -0,0 +1,330 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    ed25519_program,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    system_program,
+    clock::Clock,
+    rent::Rent,
+    sysvar::{
+        instructions::{load_current_index_checked, load_instruction_at_checked},
+        Sysvar,
+    },
+};
+use serde::{Deserialize, Serialize};
+use spl_token_2022::{extension::StateWithExtensions, state::Account};
+
+use crate::{error::TokenError, process_transfer_as};
+
+// Seed of the PDA owners approve as delegate so relayers can move their tokens
+// with a signed permit, together with the mint
+pub const PERMIT_DELEGATE_SEED: &[u8] = b"permit_delegate";
+
+// Seed of the per owner PDA holding the next permit nonce, together with the
+// mint and the owner
+pub const PERMIT_NONCE_SEED: &[u8] = b"permit_nonce";
+
+// Size of a bincode serialized PermitNonce
+pub const PERMIT_NONCE_ACCOUNT_LEN: usize = 8;
+
+// Size of a permit message: mint, from, to, amount, nonce and deadline
+pub const PERMIT_MESSAGE_LEN: usize = 32 * 3 + 8 * 3;
+
+// Permits of an owner are used in nonce order, each one once
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct PermitNonce {
+    next_nonce: u64,
+}
+
+pub fn find_permit_delegate_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PERMIT_DELEGATE_SEED, mint.as_ref()], program_id)
+}
+
+pub fn find_permit_nonce_address(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PERMIT_NONCE_SEED, mint.as_ref(), owner.as_ref()],
+        program_id,
+    )
+}
+
+// Message an owner signs to permit a transfer: the mint, the source and
+// destination token accounts, then the amount, the nonce and the deadline as
+// little endian integers
+pub fn permit_message(
+    mint: &Pubkey,
+    from: &Pubkey,
+    to: &Pubkey,
+    amount: u64,
+    nonce: u64,
+    deadline: i64,
+) -> [u8; PERMIT_MESSAGE_LEN] {
+    let mut message = [0; PERMIT_MESSAGE_LEN];
+    message[..32].copy_from_slice(mint.as_ref());
+    message[32..64].copy_from_slice(from.as_ref());
+    message[64..96].copy_from_slice(to.as_ref());
+    message[96..104].copy_from_slice(&amount.to_le_bytes());
+    message[104..112].copy_from_slice(&nonce.to_le_bytes());
+    message[112..120].copy_from_slice(&deadline.to_le_bytes());
+    message
+}
+
+// Check that `instruction` has the Ed25519 program verify a single signature of
+// `message` by `signer`, with the signature, key and message all in its own data
+fn check_ed25519_instruction(
+    instruction: &Instruction,
+    signer: &Pubkey,
+    message: &[u8],
+) -> ProgramResult {
+    if instruction.program_id != ed25519_program::id() {
+        msg!("Permit must follow an Ed25519 signature check");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // One signature, a padding byte, then seven u16 offsets
+    let data = &instruction.data;
+    if data.first() != Some(&1) {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let offset = |index: usize| -> Result<u16, ProgramError> {
+        data.get(2 + index * 2..4 + index * 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .ok_or(ProgramError::InvalidInstructionData)
+    };
+    let signature_instruction_index = offset(1)?;
+    let public_key_offset = offset(2)?;
+    let public_key_instruction_index = offset(3)?;
+    let message_offset = offset(4)?;
+    let message_size = offset(5)?;
+    let message_instruction_index = offset(6)?;
+
+    // Data taken from other instructions could be swapped for anything
+    if [
+        signature_instruction_index,
+        public_key_instruction_index,
+        message_instruction_index,
+    ]
+    .iter()
+    .any(|&index| index != u16::MAX)
+    {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let public_key_offset = public_key_offset as usize;
+    let message_offset = message_offset as usize;
+    let signed_key = data.get(public_key_offset..public_key_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size as usize);
+    if signed_key != Some(signer.as_ref()) || signed_message != Some(message) {
+        msg!("Permit is not signed by {}", signer);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+// Transfer on behalf of an owner who signed a permit off chain. The transaction
+// must verify the owner's ed25519 signature of the permit message with the
+// Ed25519 program right before this instruction. The owner must have approved the
+// permit delegate PDA on the source token account, and the nonce must be the
+// owner's next one.
+// Accounts: the instructions sysvar, the owner's nonce PDA, the payer (signer),
+// the system program, then the accounts of `Transfer` with the permit delegate PDA
+// as authority.
+pub fn process_permit_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    nonce: u64,
+    deadline: i64,
+    idle_sell_records: u8,
+    create_destination: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+    let nonce_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let transfer_accounts = account_info_iter.as_slice();
+    let source_account = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let delegate_account = next_account_info(account_info_iter)?;
+    let _token_program = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+
+    if Clock::get()?.unix_timestamp > deadline {
+        msg!("Permit expired at {}", deadline);
+        return Err(TokenError::PermitExpired.into());
+    }
+
+    let owner = StateWithExtensions::<Account>::unpack(&source_account.data.borrow())?
+        .base
+        .owner;
+
+    // The owner signed exactly this transfer
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    let signature_index = current_index
+        .checked_sub(1)
+        .ok_or(ProgramError::MissingRequiredSignature)?;
+    let signature_instruction =
+        load_instruction_at_checked(signature_index as usize, instructions_sysvar)?;
+    check_ed25519_instruction(
+        &signature_instruction,
+        &owner,
+        &permit_message(
+            mint_account.key,
+            source_account.key,
+            destination_account.key,
+            amount,
+            nonce,
+            deadline,
+        ),
+    )?;
+
+    // Use up the nonce so the permit can't be replayed
+    let (nonce_address, nonce_bump_seed) =
+        find_permit_nonce_address(program_id, mint_account.key, &owner);
+    if nonce_account.key != &nonce_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let mut permit_nonce = if nonce_account.data_is_empty() {
+        if system_program_account.key != &system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                nonce_account.key,
+                Rent::get()?.minimum_balance(PERMIT_NONCE_ACCOUNT_LEN),
+                PERMIT_NONCE_ACCOUNT_LEN as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                nonce_account.clone(),
+                system_program_account.clone(),
+            ],
+            &[&[
+                PERMIT_NONCE_SEED,
+                mint_account.key.as_ref(),
+                owner.as_ref(),
+                &[nonce_bump_seed],
+            ]],
+        )?;
+        PermitNonce::default()
+    } else {
+        if nonce_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        bincode::deserialize(&nonce_account.data.borrow())
+            .map_err(|_| ProgramError::InvalidAccountData)?
+    };
+    if nonce != permit_nonce.next_nonce {
+        msg!("Expected permit nonce {}", permit_nonce.next_nonce);
+        return Err(TokenError::InvalidPermitNonce.into());
+    }
+    permit_nonce.next_nonce = nonce.checked_add(1).ok_or(ProgramError::ArithmeticOverflow)?;
+    bincode::serialize_into(&mut &mut nonce_account.data.borrow_mut()[..], &permit_nonce)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
+    // The permit delegate moves the tokens, the payer funds new sell records
+    let (delegate_address, delegate_bump_seed) =
+        find_permit_delegate_address(program_id, mint_account.key);
+    if delegate_account.key != &delegate_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    process_transfer_as(
+        program_id,
+        transfer_accounts,
+        amount,
+        idle_sell_records,
+        create_destination,
+        Some(payer),
+        &[&[
+            PERMIT_DELEGATE_SEED,
+            mint_account.key.as_ref(),
+            &[delegate_bump_seed],
+        ]],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ed25519 program instruction data holding the signature, key and message
+    fn ed25519_instruction(signer: &Pubkey, message: &[u8]) -> Instruction {
+        let public_key_offset: u16 = 16;
+        let signature_offset = public_key_offset + 32;
+        let message_offset = signature_offset + 64;
+        let mut data = vec![1, 0];
+        for offset in [
+            signature_offset,
+            u16::MAX,
+            public_key_offset,
+            u16::MAX,
+            message_offset,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(message);
+        Instruction {
+            program_id: ed25519_program::id(),
+            accounts: vec![],
+            data,
+        }
+    }
+
+    #[test]
+    fn test_check_ed25519_instruction() {
+        let owner = Pubkey::new_unique();
+        let message = permit_message(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_000,
+            0,
+            1_700_000_000,
+        );
+        let instruction = ed25519_instruction(&owner, &message);
+        assert_eq!(check_ed25519_instruction(&instruction, &owner, &message), Ok(()));
+
+        // Another signer or another message
+        assert_eq!(
+            check_ed25519_instruction(&instruction, &Pubkey::new_unique(), &message),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        let mut other_message = message;
+        other_message[100] ^= 1;
+        assert_eq!(
+            check_ed25519_instruction(&instruction, &owner, &other_message),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // Data pointing into another instruction
+        let mut foreign_instruction = instruction.clone();
+        foreign_instruction.data[14..16].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(
+            check_ed25519_instruction(&foreign_instruction, &owner, &message),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // Not the Ed25519 program
+        let mut other_program = instruction;
+        other_program.program_id = Pubkey::new_unique();
+        assert_eq!(
+            check_ed25519_instruction(&other_program, &owner, &message),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+}
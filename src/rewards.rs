@@ -1,35 +1,52 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
-    program_error::ProgramError,
+    program_error::{PrintProgramError, ProgramError},
     pubkey::Pubkey,
     program::invoke,
     clock::Clock,
     sysvar::Sysvar,
 };
+use serde::{Deserialize, Serialize};
 use spl_token_2022::{
+    extension::StateWithExtensions,
     instruction as token_instruction,
     state::{Account, Mint},
 };
 use std::collections::HashMap;
 
-// Declare the program's entrypoint
-entrypoint!(process_instruction);
+use crate::{error::RewardsError, events, multisig};
 
-// Program entrypoint implementation
+// Declare the program's entrypoint when building the rewards program
+#[cfg(feature = "rewards-program")]
+solana_program::entrypoint!(process_instruction);
+
+// Program entrypoint implementation, logging the message of the error it fails with
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
+) -> ProgramResult {
+    if let Err(error) = process_rewards_instruction(program_id, accounts, instruction_data) {
+        error.print::<RewardsError>();
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn process_rewards_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let instruction = RewardsInstruction::unpack(instruction_data)?;
 
     match instruction {
-        RewardsInstruction::InitializeRewardsPool => {
+        RewardsInstruction::InitializeRewardsPool { min_holder_balance } => {
             msg!("Instruction: InitializeRewardsPool");
-            process_initialize_rewards_pool(program_id, accounts)
+            process_initialize_rewards_pool(program_id, accounts, min_holder_balance)
         }
         RewardsInstruction::SwapFeesForWBTC => {
             msg!("Instruction: SwapFeesForWBTC");
@@ -43,68 +60,383 @@ pub fn process_instruction(
             msg!("Instruction: AddLiquidity");
             process_add_liquidity(program_id, accounts)
         }
+        RewardsInstruction::UpdateHolderBalance { balance } => {
+            msg!("Instruction: UpdateHolderBalance");
+            process_update_holder_balance(program_id, accounts, balance)
+        }
+        RewardsInstruction::ProposeAuthority { new_authority } => {
+            msg!("Instruction: ProposeAuthority");
+            process_propose_authority(program_id, accounts, new_authority)
+        }
+        RewardsInstruction::AcceptAuthority => {
+            msg!("Instruction: AcceptAuthority");
+            process_accept_authority(program_id, accounts)
+        }
+        RewardsInstruction::InitializeMultisig { threshold } => {
+            msg!("Instruction: InitializeMultisig");
+            multisig::process_initialize_multisig(program_id, accounts, threshold)
+        }
+        RewardsInstruction::ClaimRewards => {
+            msg!("Instruction: ClaimRewards");
+            process_claim_rewards(program_id, accounts)
+        }
+        RewardsInstruction::SetLockAuthority { lock_authority } => {
+            msg!("Instruction: SetLockAuthority");
+            process_set_lock_authority(program_id, accounts, lock_authority)
+        }
+        RewardsInstruction::UpdateLockedBalance {
+            balance,
+            weight_basis_points,
+        } => {
+            msg!("Instruction: UpdateLockedBalance");
+            process_update_locked_balance(program_id, accounts, balance, weight_basis_points)
+        }
     }
 }
 
 #[derive(Debug)]
 enum RewardsInstruction {
-    InitializeRewardsPool,
+    InitializeRewardsPool {
+        min_holder_balance: u64,
+    },
     SwapFeesForWBTC,
     DistributeRewards,
     AddLiquidity,
+    UpdateHolderBalance {
+        balance: u64,
+    },
+    ProposeAuthority {
+        new_authority: Option<Pubkey>, // None cancels the pending proposal
+    },
+    AcceptAuthority,
+    InitializeMultisig {
+        threshold: u8,
+    },
+    ClaimRewards,
+    SetLockAuthority {
+        lock_authority: Option<Pubkey>, // None stops recording locked positions
+    },
+    UpdateLockedBalance {
+        balance: u64,
+        weight_basis_points: u16, // Reward weight of the position, 10000 = 1x
+    },
 }
 
 impl RewardsInstruction {
     fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&tag, _) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+        let (&tag, rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
         Ok(match tag {
-            0 => Self::InitializeRewardsPool,
+            0 => {
+                let min_holder_balance = rest
+                    .get(..8)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::InitializeRewardsPool { min_holder_balance }
+            }
             1 => Self::SwapFeesForWBTC,
             2 => Self::DistributeRewards,
             3 => Self::AddLiquidity,
+            4 => {
+                let balance = rest
+                    .get(..8)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::UpdateHolderBalance { balance }
+            }
+            5 => {
+                let new_authority = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(Pubkey::new_from_array)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::ProposeAuthority {
+                    new_authority: (new_authority != Pubkey::default()).then_some(new_authority),
+                }
+            }
+            6 => Self::AcceptAuthority,
+            7 => {
+                let (&threshold, _) = rest
+                    .split_first()
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::InitializeMultisig { threshold }
+            }
+            8 => Self::ClaimRewards,
+            9 => {
+                let lock_authority = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(Pubkey::new_from_array)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::SetLockAuthority {
+                    lock_authority: (lock_authority != Pubkey::default()).then_some(lock_authority),
+                }
+            }
+            10 => {
+                let balance = rest
+                    .get(..8)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                let weight_basis_points = rest
+                    .get(8..10)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::UpdateLockedBalance {
+                    balance,
+                    weight_basis_points,
+                }
+            }
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
 }
 
-#[derive(Debug)]
+// Scale of `RewardsPool::reward_per_share`, keeping the precision of small payouts
+// spread over a large supply
+const REWARD_PER_SHARE_SCALE: u128 = 1_000_000_000_000;
+
+#[derive(Debug, Serialize, Deserialize)]
 struct RewardsPool {
     last_distribution_time: i64,
     total_wbtc_balance: u64,
-    token_holders: HashMap<Pubkey, u64>,
+    token_holders: HashMap<Pubkey, HolderRewards>, // Keyed by holder token account
+    total_eligible_supply: u64, // Sum of the recorded balances, locked ones weighted
+    token_mint: Pubkey, // Mint whose holders earn rewards
+    min_holder_balance: u64, // Smallest balance recorded, keeping dust out of the pool
+    wbtc_mint: Pubkey,
+    wbtc_account: Pubkey, // WBTC account of the pool, paying out rewards
+    reward_per_share: u128, // WBTC earned per recorded token so far, scaled
     reserve_wallet: Pubkey,
     last_liquidity_add_time: i64,
     liquidity_threshold: u64,
+    admin: Pubkey,
+    pending_admin: Option<Pubkey>, // Proposed admin waiting to accept
+    lock_authority: Option<Pubkey>, // Token program PDA holding locked tokens
+    locked_positions: HashMap<Pubkey, LockedPosition>, // Locked tokens by position account
+}
+
+// Tokens locked by the token program on behalf of a holder, e.g. in a vesting
+// escrow or a stake. They earn rewards for the holder token account, boosted by
+// the weight of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct LockedPosition {
+    holder: Pubkey,
+    balance: u64,
+    weight_basis_points: u16, // 10000 = 1x
+}
+
+impl LockedPosition {
+    // Balance the position earns rewards for
+    fn weighted_balance(&self) -> u64 {
+        let weighted_balance =
+            (self.balance as u128) * (self.weight_basis_points as u128) / 10_000;
+        weighted_balance.try_into().unwrap_or(u64::MAX)
+    }
+}
+
+// Rewards bookkeeping of one holder token account. Holders claim what they earned
+// with ClaimRewards instead of the pool paying everyone out in one instruction.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct HolderRewards {
+    balance: u64, // Recorded balance
+    reward_debt: u128, // Scaled rewards per share already accounted for the balance
+    unclaimed: u64, // WBTC earned and not claimed yet
+    locked_balance: u64, // Weighted sum of the holder's locked positions, earning with the balance
+}
+
+impl HolderRewards {
+    // Balance the holder earns rewards on
+    fn earning_balance(&self) -> u64 {
+        self.balance.saturating_add(self.locked_balance)
+    }
+}
+
+impl RewardsPool {
+    // Record `balance` for `holder`, dropping holders under the minimum balance and
+    // keeping the total eligible supply in line. Rewards earned on the previous
+    // balance stay claimable. The recorded balances never add up past the mint's
+    // supply, so the total can't overflow.
+    fn set_holder_balance(&mut self, holder: Pubkey, balance: u64) {
+        let recorded_balance = if balance < self.min_holder_balance.max(1) { 0 } else { balance };
+        let mut holder_rewards = self.settle_holder(&holder);
+        self.total_eligible_supply = self
+            .total_eligible_supply
+            .saturating_sub(holder_rewards.balance)
+            .saturating_add(recorded_balance);
+        holder_rewards.balance = recorded_balance;
+        holder_rewards.reward_debt = self.accrued_rewards(holder_rewards.earning_balance());
+        self.store_holder(holder, holder_rewards);
+    }
+
+    // Share `amount` of WBTC between the recorded balances. Returns false, sharing
+    // nothing, while no balance is recorded.
+    fn add_rewards(&mut self, amount: u64) -> bool {
+        if self.total_eligible_supply == 0 {
+            return false;
+        }
+        let added_per_share = (amount as u128).saturating_mul(REWARD_PER_SHARE_SCALE)
+            / self.total_eligible_supply as u128;
+        self.reward_per_share = self.reward_per_share.saturating_add(added_per_share);
+        true
+    }
+
+    // Take the WBTC `holder` has earned so far out of the pool
+    fn claim(&mut self, holder: &Pubkey) -> u64 {
+        let mut holder_rewards = self.settle_holder(holder);
+        let claimed = std::mem::take(&mut holder_rewards.unclaimed);
+        self.store_holder(*holder, holder_rewards);
+        claimed
+    }
+
+    // Remove `holder` from the pool with what it earned up to now added to its
+    // unclaimed rewards
+    fn settle_holder(&mut self, holder: &Pubkey) -> HolderRewards {
+        let mut holder_rewards = self.token_holders.remove(holder).unwrap_or_default();
+        let earned = self
+            .accrued_rewards(holder_rewards.earning_balance())
+            .saturating_sub(holder_rewards.reward_debt)
+            / REWARD_PER_SHARE_SCALE;
+        holder_rewards.unclaimed = holder_rewards
+            .unclaimed
+            .saturating_add(earned.try_into().unwrap_or(u64::MAX));
+        holder_rewards.reward_debt = self.accrued_rewards(holder_rewards.earning_balance());
+        holder_rewards
+    }
+
+    // Keep `holder` only while it has a balance or rewards to claim
+    fn store_holder(&mut self, holder: Pubkey, holder_rewards: HolderRewards) {
+        if holder_rewards.earning_balance() > 0 || holder_rewards.unclaimed > 0 {
+            self.token_holders.insert(holder, holder_rewards);
+        }
+    }
+
+    // Scaled rewards per share earned by `balance` since the pool was created
+    fn accrued_rewards(&self, balance: u64) -> u128 {
+        (balance as u128).saturating_mul(self.reward_per_share)
+    }
+
+    // Only the pool's own WBTC account, of the WBTC mint, pays out
+    fn check_wbtc_accounts(
+        &self,
+        wbtc_account: &AccountInfo,
+        wbtc_mint: &AccountInfo,
+        wbtc_token_program: &AccountInfo,
+    ) -> ProgramResult {
+        if wbtc_account.key != &self.wbtc_account || wbtc_mint.key != &self.wbtc_mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if wbtc_mint.owner != wbtc_token_program.key {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+
+    // Record `balance` locked in `position` for `holder`, earning with
+    // `weight_basis_points`. The holder earns on the weighted balance of its locked
+    // positions as on its own balance, whatever the minimum balance.
+    fn set_locked_balance(
+        &mut self,
+        position: Pubkey,
+        holder: Pubkey,
+        balance: u64,
+        weight_basis_points: u16,
+    ) {
+        let locked_position = LockedPosition {
+            holder,
+            balance,
+            weight_basis_points,
+        };
+        let weighted_balance = locked_position.weighted_balance();
+        let previous_position = if balance == 0 {
+            self.locked_positions.remove(&position)
+        } else {
+            self.locked_positions.insert(position, locked_position)
+        };
+        if let Some(previous_position) = previous_position {
+            self.update_locked_balance(previous_position.holder, |locked_balance| {
+                locked_balance.saturating_sub(previous_position.weighted_balance())
+            });
+        }
+        self.update_locked_balance(holder, |locked_balance| {
+            locked_balance.saturating_add(weighted_balance)
+        });
+    }
+
+    // Apply `update` to the locked balance of `holder`, keeping the total eligible
+    // supply in line
+    fn update_locked_balance(&mut self, holder: Pubkey, update: impl FnOnce(u64) -> u64) {
+        let mut holder_rewards = self.settle_holder(&holder);
+        let locked_balance = update(holder_rewards.locked_balance);
+        self.total_eligible_supply = self
+            .total_eligible_supply
+            .saturating_sub(holder_rewards.locked_balance)
+            .saturating_add(locked_balance);
+        holder_rewards.locked_balance = locked_balance;
+        holder_rewards.reward_debt = self.accrued_rewards(holder_rewards.earning_balance());
+        self.store_holder(holder, holder_rewards);
+    }
 }
 
 fn process_initialize_rewards_pool(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    min_holder_balance: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let rewards_pool_account = next_account_info(account_info_iter)?;
     let wbtc_mint = next_account_info(account_info_iter)?;
     let wbtc_account = next_account_info(account_info_iter)?;
     let reserve_wallet = next_account_info(account_info_iter)?;
+    let token_mint = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
 
     // Verify the rewards pool account is owned by the program
     if rewards_pool_account.owner != program_id {
         return Err(ProgramError::IncorrectProgramId);
     }
 
+    // WBTC may be an SPL Token or a Token-2022 mint, and the pool's WBTC account
+    // must hold it
+    spl_token_2022::check_spl_token_program_account(wbtc_mint.owner)?;
+    if wbtc_account.owner != wbtc_mint.owner {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let wbtc_account_mint = StateWithExtensions::<Account>::unpack(&wbtc_account.data.borrow())?
+        .base
+        .mint;
+    if wbtc_account_mint != *wbtc_mint.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The admin may be a multisig, signing through the remaining accounts
+    multisig::check_authority(program_id, admin.key, admin, account_info_iter.as_slice())?;
+
     // Initialize rewards pool
     let rewards_pool = RewardsPool {
         last_distribution_time: 0,
         total_wbtc_balance: 0,
         token_holders: HashMap::new(),
+        total_eligible_supply: 0,
+        token_mint: *token_mint.key,
+        min_holder_balance,
+        wbtc_mint: *wbtc_mint.key,
+        wbtc_account: *wbtc_account.key,
+        reward_per_share: 0,
         reserve_wallet: *reserve_wallet.key,
         last_liquidity_add_time: 0,
         liquidity_threshold: 100_000_000, // 0.1 WBTC (8 decimals)
+        admin: *admin.key,
+        pending_admin: None,
+        lock_authority: None,
+        locked_positions: HashMap::new(),
     };
 
     let mut pool_data = rewards_pool_account.data.borrow_mut();
     bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
 
     Ok(())
 }
@@ -123,7 +455,7 @@ fn process_swap_fees_for_wbtc(
     // Get current rewards pool state
     let pool_data = rewards_pool_account.data.borrow();
     let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+        .map_err(|_| RewardsError::InvalidPoolData)?;
 
     // TODO: Implement actual swap logic using Jupiter or other DEX
     // This is a placeholder for the swap implementation
@@ -146,6 +478,10 @@ fn process_swap_fees_for_wbtc(
     Ok(())
 }
 
+// Send half of the pool's WBTC to the reserve wallet and share the other half
+// between the recorded holders, who collect it with ClaimRewards. Accounts: the
+// rewards pool, the pool's WBTC account, the clock, the reserve wallet, the WBTC
+// mint and the WBTC token program.
 fn process_distribute_rewards(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -155,18 +491,28 @@ fn process_distribute_rewards(
     let wbtc_account = next_account_info(account_info_iter)?;
     let clock = next_account_info(account_info_iter)?;
     let reserve_wallet = next_account_info(account_info_iter)?;
+    let wbtc_mint = next_account_info(account_info_iter)?;
+    let wbtc_token_program = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
     // Get current rewards pool state
     let mut pool_data = rewards_pool_account.data.borrow_mut();
     let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+        .map_err(|_| RewardsError::InvalidPoolData)?;
+    rewards_pool.check_wbtc_accounts(wbtc_account, wbtc_mint, wbtc_token_program)?;
+    if reserve_wallet.key != &rewards_pool.reserve_wallet {
+        return Err(ProgramError::InvalidAccountData);
+    }
 
     // Get current time
     let current_time = Clock::get()?.unix_timestamp;
 
     // Check if 30 minutes have passed since last distribution
     if current_time - rewards_pool.last_distribution_time < 1800 {
-        return Err(ProgramError::InvalidInstructionData);
+        return Err(RewardsError::DistributionTooEarly.into());
     }
 
     // Calculate 50% of WBTC balance for distribution
@@ -174,7 +520,7 @@ fn process_distribute_rewards(
 
     // Transfer 50% to reserve wallet
     let reserve_transfer_instruction = token_instruction::transfer(
-        program_id,
+        wbtc_token_program.key,
         wbtc_account.key,
         reserve_wallet.key,
         rewards_pool_account.key,
@@ -188,47 +534,121 @@ fn process_distribute_rewards(
             wbtc_account.clone(),
             reserve_wallet.clone(),
             rewards_pool_account.clone(),
+            wbtc_token_program.clone(),
         ],
     )?;
+    rewards_pool.total_wbtc_balance -= distribution_amount;
 
-    // Distribute remaining 50% to token holders
-    for (holder, balance) in rewards_pool.token_holders.iter() {
-        let holder_wbtc_account = next_account_info(account_info_iter)?;
-        
-        // Calculate holder's share
-        let holder_share = (distribution_amount as u128)
-            .checked_mul(*balance as u128)
-            .ok_or(ProgramError::Overflow)?
-            .checked_div(rewards_pool.total_wbtc_balance as u128)
-            .ok_or(ProgramError::Overflow)? as u64;
-
-        // Transfer WBTC to holder
-        let transfer_instruction = token_instruction::transfer(
-            program_id,
-            wbtc_account.key,
-            holder_wbtc_account.key,
-            rewards_pool_account.key,
-            &[],
-            holder_share,
-        )?;
-
-        invoke(
-            &transfer_instruction,
-            &[
-                wbtc_account.clone(),
-                holder_wbtc_account.clone(),
-                rewards_pool_account.clone(),
-            ],
-        )?;
+    // The remaining 50% stays in the pool's WBTC account until the holders claim
+    // it. With no holders recorded it waits for the next distribution.
+    let mut holders_amount = 0;
+    if rewards_pool.add_rewards(distribution_amount) {
+        rewards_pool.total_wbtc_balance -= distribution_amount;
+        holders_amount = distribution_amount;
     }
 
+    events::Event::RewardsDistributed(events::RewardsDistributed {
+        pool: *rewards_pool_account.key,
+        reserve_amount: distribution_amount,
+        holders_amount,
+        eligible_supply: rewards_pool.total_eligible_supply,
+        timestamp: current_time,
+    })
+    .emit();
+
     // Update rewards pool state
     rewards_pool.last_distribution_time = current_time;
-    rewards_pool.total_wbtc_balance = 0; // All WBTC has been distributed
 
     // Save updated state
     bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
+    Ok(())
+}
+
+// Pay out the WBTC a holder token account has earned to the WBTC associated token
+// account of its owner, created if it doesn't exist. Anyone may claim on behalf
+// of a holder since the rewards only ever go to the owner. Accounts: the rewards
+// pool, the pool's WBTC account, the holder token account, its owner, the owner's
+// WBTC associated token account, the payer (signer) funding it, the WBTC mint, the
+// WBTC token program, the system program and the associated token account program.
+fn process_claim_rewards(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let wbtc_account = next_account_info(account_info_iter)?;
+    let holder_account = next_account_info(account_info_iter)?;
+    let holder_wallet = next_account_info(account_info_iter)?;
+    let holder_wbtc_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let wbtc_mint = next_account_info(account_info_iter)?;
+    let wbtc_token_program = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let associated_token_program = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if holder_account.owner != &spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool_data = rewards_pool_account.data.borrow_mut();
+    let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    rewards_pool.check_wbtc_accounts(wbtc_account, wbtc_mint, wbtc_token_program)?;
+
+    // Rewards go to the WBTC associated token account of the holder's owner
+    let holder = StateWithExtensions::<Account>::unpack(&holder_account.data.borrow())?.base;
+    if holder.mint != rewards_pool.token_mint || holder.owner != *holder_wallet.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let claimed = rewards_pool.claim(holder_account.key);
+    if claimed == 0 {
+        msg!("Nothing to claim for {}", holder_account.key);
+        return Ok(());
+    }
+
+    crate::create_associated_token_account_idempotent(
+        payer,
+        holder_wbtc_account,
+        holder_wallet,
+        wbtc_mint,
+        system_program_account,
+        wbtc_token_program,
+        associated_token_program,
+    )?;
+
+    let transfer_instruction = token_instruction::transfer(
+        wbtc_token_program.key,
+        wbtc_account.key,
+        holder_wbtc_account.key,
+        rewards_pool_account.key,
+        &[],
+        claimed,
+    )?;
+
+    invoke(
+        &transfer_instruction,
+        &[
+            wbtc_account.clone(),
+            holder_wbtc_account.clone(),
+            rewards_pool_account.clone(),
+            wbtc_token_program.clone(),
+        ],
+    )?;
+    msg!("Claimed {} WBTC for {}", claimed, holder_account.key);
+
+    events::Event::HolderPaid(events::HolderPaid {
+        pool: *rewards_pool_account.key,
+        holder: *holder_account.key,
+        owner: holder.owner,
+        amount: claimed,
+    })
+    .emit();
+
+    bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
 
     Ok(())
 }
@@ -246,14 +666,14 @@ fn process_add_liquidity(
     // Get current rewards pool state
     let pool_data = rewards_pool_account.data.borrow();
     let rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+        .map_err(|_| RewardsError::InvalidPoolData)?;
 
     // Get current time
     let current_time = Clock::get()?.unix_timestamp;
 
     // Check if 30 minutes have passed since last liquidity addition
     if current_time - rewards_pool.last_liquidity_add_time < 1800 {
-        return Err(ProgramError::InvalidInstructionData);
+        return Err(RewardsError::LiquidityTooEarly.into());
     }
 
     // TODO: Implement actual liquidity addition logic using DEX
@@ -272,6 +692,272 @@ fn process_add_liquidity(
         ],
     )?;
 
+    events::Event::LiquidityAdded(events::LiquidityAdded {
+        pool: *rewards_pool_account.key,
+        reserve_wallet: *reserve_wallet.key,
+        timestamp: current_time,
+    })
+    .emit();
+
+    Ok(())
+}
+
+// Record the balance of a holder token account of the pool's mint. The balance
+// must match the token account, so anyone may keep the pool in sync. Transfers
+// sync both sides through here, so running out of room skips the holder
+// instead of failing.
+fn process_update_holder_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    balance: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let holder_account = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if holder_account.owner != &spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool_data = rewards_pool_account.data.borrow_mut();
+    let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
+        .map_err(|_| RewardsError::InvalidPoolData)?;
+
+    let holder = StateWithExtensions::<Account>::unpack(&holder_account.data.borrow())?.base;
+    if holder.mint != rewards_pool.token_mint {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if holder.amount != balance {
+        msg!("Balance {} does not match the holder's {}", balance, holder.amount);
+        return Err(RewardsError::BalanceMismatch.into());
+    }
+
+    // Locked tokens only earn through their locked position
+    let balance = if rewards_pool.lock_authority == Some(holder.owner) {
+        0
+    } else {
+        balance
+    };
+    rewards_pool.set_holder_balance(*holder_account.key, balance);
+
+    let pool_len = bincode::serialized_size(&rewards_pool)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    if pool_len > pool_data.len() as u64 {
+        msg!("Rewards pool is full, holder {} not recorded", holder_account.key);
+        return Ok(());
+    }
+
+    bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
+    Ok(())
+}
+
+// Create an UpdateHolderBalance instruction recording `balance` for the
+// `holder` token account
+pub fn create_update_holder_balance_instruction(
+    rewards_program_id: &Pubkey,
+    rewards_pool: &Pubkey,
+    holder: &Pubkey,
+    balance: u64,
+) -> Result<Instruction, ProgramError> {
+    let mut data = vec![4]; // UpdateHolderBalance instruction tag
+    data.extend_from_slice(&balance.to_le_bytes());
+
+    Ok(Instruction {
+        program_id: *rewards_program_id,
+        accounts: vec![
+            AccountMeta::new(*rewards_pool, false),
+            AccountMeta::new_readonly(*holder, false),
+        ],
+        data,
+    })
+}
+
+// Record the tokens locked in a position for a holder token account. Only the
+// lock authority may record locked positions, as the balance can't be checked
+// against a token account. Accounts: the rewards pool, the lock authority
+// (signer), the position account and the holder token account. Like holder
+// balances, positions are skipped once the pool runs out of room.
+fn process_update_locked_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    balance: u64,
+    weight_basis_points: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let lock_authority = next_account_info(account_info_iter)?;
+    let position_account = next_account_info(account_info_iter)?;
+    let holder_account = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool_data = rewards_pool_account.data.borrow_mut();
+    let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
+        .map_err(|_| RewardsError::InvalidPoolData)?;
+
+    if !lock_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if rewards_pool.lock_authority != Some(*lock_authority.key) {
+        return Err(RewardsError::InvalidLockAuthority.into());
+    }
+
+    rewards_pool.set_locked_balance(
+        *position_account.key,
+        *holder_account.key,
+        balance,
+        weight_basis_points,
+    );
+
+    let pool_len = bincode::serialized_size(&rewards_pool)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    if pool_len > pool_data.len() as u64 {
+        msg!("Rewards pool is full, position {} not recorded", position_account.key);
+        return Ok(());
+    }
+
+    bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
+    Ok(())
+}
+
+// Create an UpdateLockedBalance instruction recording `balance` locked in
+// `position` for the `holder` token account, earning with `weight_basis_points`
+pub fn create_update_locked_balance_instruction(
+    rewards_program_id: &Pubkey,
+    rewards_pool: &Pubkey,
+    lock_authority: &Pubkey,
+    position: &Pubkey,
+    holder: &Pubkey,
+    balance: u64,
+    weight_basis_points: u16,
+) -> Result<Instruction, ProgramError> {
+    let mut data = vec![10]; // UpdateLockedBalance instruction tag
+    data.extend_from_slice(&balance.to_le_bytes());
+    data.extend_from_slice(&weight_basis_points.to_le_bytes());
+
+    Ok(Instruction {
+        program_id: *rewards_program_id,
+        accounts: vec![
+            AccountMeta::new(*rewards_pool, false),
+            AccountMeta::new_readonly(*lock_authority, true),
+            AccountMeta::new_readonly(*position, false),
+            AccountMeta::new_readonly(*holder, false),
+        ],
+        data,
+    })
+}
+
+// Set the token program PDA allowed to record locked positions, whose own token
+// accounts no longer count as holders
+fn process_set_lock_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lock_authority: Option<Pubkey>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool_data = rewards_pool_account.data.borrow_mut();
+    let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
+        .map_err(|_| RewardsError::InvalidPoolData)?;
+
+    multisig::check_authority(
+        program_id,
+        &rewards_pool.admin,
+        admin,
+        account_info_iter.as_slice(),
+    )?;
+
+    rewards_pool.lock_authority = lock_authority;
+
+    bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
+    Ok(())
+}
+
+// Propose a new admin for the rewards pool, or cancel the pending proposal
+fn process_propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Option<Pubkey>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let admin = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool_data = rewards_pool_account.data.borrow_mut();
+    let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
+        .map_err(|_| RewardsError::InvalidPoolData)?;
+
+    // Only the current admin may propose a new one
+    multisig::check_authority(
+        program_id,
+        &rewards_pool.admin,
+        admin,
+        account_info_iter.as_slice(),
+    )?;
+
+    rewards_pool.pending_admin = new_authority;
+
+    bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
+    Ok(())
+}
+
+// Take over the rewards pool as the proposed admin
+fn process_accept_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let new_admin = next_account_info(account_info_iter)?;
+
+    if rewards_pool_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut pool_data = rewards_pool_account.data.borrow_mut();
+    let mut rewards_pool: RewardsPool = bincode::deserialize(&pool_data)
+        .map_err(|_| RewardsError::InvalidPoolData)?;
+
+    let pending_admin = rewards_pool
+        .pending_admin
+        .ok_or(RewardsError::NoPendingAdmin)?;
+    multisig::check_authority(
+        program_id,
+        &pending_admin,
+        new_admin,
+        account_info_iter.as_slice(),
+    )?;
+
+    rewards_pool.admin = pending_admin;
+    rewards_pool.pending_admin = None;
+    msg!("Rewards pool admin is now {}", new_admin.key);
+
+    bincode::serialize_into(&mut &mut pool_data[..], &rewards_pool)
+        .map_err(|_| ProgramError::AccountDataTooSmall)?;
+
     Ok(())
 }
 
@@ -312,4 +998,108 @@ mod tests {
     fn test_sanity() {
         // Add tests here
     }
-} 
\ No newline at end of file
+
+    fn test_rewards_pool() -> RewardsPool {
+        RewardsPool {
+            last_distribution_time: 0,
+            total_wbtc_balance: 0,
+            token_holders: HashMap::new(),
+            total_eligible_supply: 0,
+            token_mint: Pubkey::new_unique(),
+            min_holder_balance: 100,
+            wbtc_mint: Pubkey::new_unique(),
+            wbtc_account: Pubkey::new_unique(),
+            reward_per_share: 0,
+            reserve_wallet: Pubkey::new_unique(),
+            last_liquidity_add_time: 0,
+            liquidity_threshold: 0,
+            admin: Pubkey::new_unique(),
+            pending_admin: None,
+            lock_authority: None,
+            locked_positions: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_set_holder_balance() {
+        let mut rewards_pool = test_rewards_pool();
+        let holder = Pubkey::new_unique();
+        let dust_holder = Pubkey::new_unique();
+
+        rewards_pool.set_holder_balance(holder, 700);
+        rewards_pool.set_holder_balance(dust_holder, 99);
+        assert_eq!(rewards_pool.token_holders[&holder].balance, 700);
+        assert!(!rewards_pool.token_holders.contains_key(&dust_holder));
+
+        // Dropping under the minimum removes the holder
+        rewards_pool.set_holder_balance(holder, 50);
+        assert!(rewards_pool.token_holders.is_empty());
+        assert_eq!(rewards_pool.total_eligible_supply, 0);
+
+        // Burning part of a position lowers the eligible supply right away
+        let other_holder = Pubkey::new_unique();
+        rewards_pool.set_holder_balance(holder, 700);
+        rewards_pool.set_holder_balance(other_holder, 300);
+        assert_eq!(rewards_pool.total_eligible_supply, 1_000);
+        rewards_pool.set_holder_balance(holder, 200);
+        assert_eq!(rewards_pool.total_eligible_supply, 500);
+    }
+
+    #[test]
+    fn test_claim() {
+        let mut rewards_pool = test_rewards_pool();
+        let holder = Pubkey::new_unique();
+        let other_holder = Pubkey::new_unique();
+
+        // Nothing is shared while no balance is recorded
+        assert!(!rewards_pool.add_rewards(1_000));
+
+        rewards_pool.set_holder_balance(holder, 750);
+        rewards_pool.set_holder_balance(other_holder, 250);
+        assert!(rewards_pool.add_rewards(1_000));
+
+        // Selling out keeps what was earned before claimable
+        rewards_pool.set_holder_balance(other_holder, 0);
+        assert!(rewards_pool.add_rewards(1_500));
+        assert_eq!(rewards_pool.claim(&holder), 2_250);
+        assert_eq!(rewards_pool.claim(&holder), 0);
+        assert_eq!(rewards_pool.claim(&other_holder), 250);
+        assert!(!rewards_pool.token_holders.contains_key(&other_holder));
+
+        // A holder only earns on what it held when the rewards came in
+        let new_holder = Pubkey::new_unique();
+        rewards_pool.set_holder_balance(new_holder, 750);
+        assert_eq!(rewards_pool.claim(&new_holder), 0);
+        assert!(rewards_pool.add_rewards(1_500));
+        assert_eq!(rewards_pool.claim(&new_holder), 750);
+        assert_eq!(rewards_pool.claim(&holder), 750);
+    }
+
+    #[test]
+    fn test_set_locked_balance() {
+        let mut rewards_pool = test_rewards_pool();
+        let holder = Pubkey::new_unique();
+        let other_holder = Pubkey::new_unique();
+        rewards_pool.set_holder_balance(holder, 200);
+
+        // Locked positions add their weighted balance to the eligible supply, one
+        // record per position, even under the minimum balance
+        let position = Pubkey::new_unique();
+        rewards_pool.set_locked_balance(position, other_holder, 400, 10_000);
+        rewards_pool.set_locked_balance(Pubkey::new_unique(), holder, 25, 20_000);
+        assert_eq!(rewards_pool.total_eligible_supply, 650);
+        rewards_pool.set_locked_balance(position, other_holder, 100, 15_000);
+        assert_eq!(rewards_pool.total_eligible_supply, 400);
+        assert_eq!(rewards_pool.token_holders[&other_holder].locked_balance, 150);
+
+        // Locked tokens earn for their holder by weight
+        assert!(rewards_pool.add_rewards(800));
+        assert_eq!(rewards_pool.claim(&holder), 500);
+        assert_eq!(rewards_pool.claim(&other_holder), 300);
+
+        rewards_pool.set_locked_balance(position, other_holder, 0, 15_000);
+        assert_eq!(rewards_pool.total_eligible_supply, 250);
+        assert!(!rewards_pool.locked_positions.contains_key(&position));
+        assert!(!rewards_pool.token_holders.contains_key(&other_holder));
+    }
+}
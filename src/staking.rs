@@ -0,0 +1,376 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    system_program,
+    clock::Clock,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+use serde::{Deserialize, Serialize};
+use spl_token_2022::{extension::StateWithExtensions, state::Account};
+
+use crate::{
+    calculate_fee, check_rewards_accounts, create_locked_token_account, error::TokenError,
+    find_lock_authority_address, load_fee_config, next_fee_config_account, record_locked_balance,
+    token_account_amount, transfer_from_locked_account, transfer_tokens,
+    FULL_WEIGHT_BASIS_POINTS,
+};
+
+// Seed of stake PDAs, together with the mint and the key the stake was created with
+pub const STAKE_SEED: &[u8] = b"stake";
+
+// Seed of the vault token account of a stake, together with the stake address.
+// Vaults are held by the lock authority PDA of the mint.
+pub const STAKE_VAULT_SEED: &[u8] = b"stake_vault";
+
+// Size of a bincode serialized Stake
+pub const STAKE_ACCOUNT_LEN: usize = 32 * 4 + 8 + 8 + 2 + 1;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// Reward weight of a stake by how long it is locked for: the longest lock it
+// reaches sets its weight
+const STAKE_LOCK_TIERS: [(u64, u16); 3] = [
+    (0, FULL_WEIGHT_BASIS_POINTS), // 1x without a lock
+    (90 * SECONDS_PER_DAY, 15_000), // 1.5x for 3 months
+    (365 * SECONDS_PER_DAY, 20_000), // 2x for 12 months
+];
+
+// Tokens locked in a vault, earning boosted rewards for the holder token account
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stake {
+    mint: Pubkey,
+    owner: Pubkey, // Wallet that may unstake
+    holder: Pubkey, // Token account the tokens came from and return to
+    create_key: Pubkey,
+    amount: u64, // Tokens in the vault
+    lock_end_timestamp: i64, // Unstaking before this is blocked or penalized
+    weight_basis_points: u16, // Reward weight, 10000 = 1x
+    bump_seed: u8,
+}
+
+impl Stake {
+    // Tokens kept back when unstaking at `timestamp`. Unstaking before the lock
+    // ends fails unless an early unstake penalty is set.
+    fn unstake_penalty(
+        &self,
+        penalty_basis_points: Option<u16>,
+        timestamp: i64,
+    ) -> Result<u64, ProgramError> {
+        if timestamp >= self.lock_end_timestamp {
+            return Ok(0);
+        }
+        match penalty_basis_points {
+            Some(penalty_basis_points) => calculate_fee(self.amount, penalty_basis_points),
+            None => {
+                msg!("Stake is locked until {}", self.lock_end_timestamp);
+                Err(TokenError::StakeLocked.into())
+            }
+        }
+    }
+}
+
+fn lock_weight_basis_points(lock_seconds: u64) -> u16 {
+    STAKE_LOCK_TIERS
+        .iter()
+        .rev()
+        .find(|(tier_seconds, _)| lock_seconds >= *tier_seconds)
+        .map_or(FULL_WEIGHT_BASIS_POINTS, |(_, weight_basis_points)| *weight_basis_points)
+}
+
+pub fn find_stake_address(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    create_key: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKE_SEED, mint.as_ref(), create_key.as_ref()], program_id)
+}
+
+pub fn find_stake_vault_address(program_id: &Pubkey, stake: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKE_VAULT_SEED, stake.as_ref()], program_id)
+}
+
+fn store_stake(stake_account: &AccountInfo, stake: &Stake) -> ProgramResult {
+    bincode::serialize_into(&mut &mut stake_account.data.borrow_mut()[..], stake)
+        .map_err(|_| ProgramError::AccountDataTooSmall)
+}
+
+// Lock `amount` from the holder token account in a new vault for `lock_seconds`.
+// Accounts: the stake PDA, the vault PDA, the key the stake is derived from, the
+// mint, the fee config PDA, the holder token account, its owner (signer), the
+// payer, the system program, the token program, the lock authority PDA, the
+// rewards program, the rewards pool and the transfer hook accounts.
+pub fn process_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    lock_seconds: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let create_key = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let holder_account = next_account_info(account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let lock_authority = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let hook_accounts = account_info_iter.as_slice();
+
+    // Vaults are Token-2022 accounts, so only native mints can be staked
+    if mint_account.owner != &spl_token_2022::id() || token_program.key != &spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if system_program_account.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let holder = StateWithExtensions::<Account>::unpack(&holder_account.data.borrow())?.base;
+    if holder.owner != *owner_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+
+    let (stake_address, bump_seed) =
+        find_stake_address(program_id, mint_account.key, create_key.key);
+    if stake_account.key != &stake_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let (vault_address, vault_bump_seed) = find_stake_vault_address(program_id, &stake_address);
+    if vault_account.key != &vault_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let (lock_authority_address, _) = find_lock_authority_address(program_id, mint_account.key);
+    if lock_authority.key != &lock_authority_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let lock_end_timestamp = i64::try_from(lock_seconds)
+        .ok()
+        .and_then(|lock_seconds| now.checked_add(lock_seconds))
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            stake_account.key,
+            Rent::get()?.minimum_balance(STAKE_ACCOUNT_LEN),
+            STAKE_ACCOUNT_LEN as u64,
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            stake_account.clone(),
+            system_program_account.clone(),
+        ],
+        &[&[
+            STAKE_SEED,
+            mint_account.key.as_ref(),
+            create_key.key.as_ref(),
+            &[bump_seed],
+        ]],
+    )?;
+    create_locked_token_account(
+        payer,
+        vault_account,
+        mint_account,
+        lock_authority,
+        system_program_account,
+        token_program,
+        &[STAKE_VAULT_SEED, stake_address.as_ref(), &[vault_bump_seed]],
+    )?;
+
+    // Fee mints withhold part of the transfer, so only what reached the vault is staked
+    transfer_tokens(
+        holder_account,
+        mint_account,
+        vault_account,
+        owner_account,
+        token_program,
+        hook_accounts,
+        amount,
+        &[],
+    )?;
+
+    let stake = Stake {
+        mint: *mint_account.key,
+        owner: *owner_account.key,
+        holder: *holder_account.key,
+        create_key: *create_key.key,
+        amount: token_account_amount(vault_account)?,
+        lock_end_timestamp,
+        weight_basis_points: lock_weight_basis_points(lock_seconds),
+        bump_seed,
+    };
+    msg!(
+        "Staked {} tokens until {} with a {} basis point weight",
+        stake.amount,
+        stake.lock_end_timestamp,
+        stake.weight_basis_points
+    );
+    store_stake(stake_account, &stake)?;
+
+    record_locked_balance(
+        program_id,
+        mint_account,
+        lock_authority,
+        rewards_program,
+        rewards_pool_account,
+        stake_account,
+        holder_account,
+        stake.amount,
+        stake.weight_basis_points,
+    )
+}
+
+// Return the staked tokens to the holder token account. Unstaking before the
+// lock ends is blocked, or charged the early unstake penalty which goes to the
+// fee collector and so back to the rewards pool. Accounts: the stake, the vault,
+// the mint, the fee config PDA, the stake owner (signer), the holder token
+// account, the fee collector, the lock authority PDA, the token program, the
+// rewards program, the rewards pool and the transfer hook accounts.
+pub fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let stake_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+    let holder_account = next_account_info(account_info_iter)?;
+    let fee_collector_account = next_account_info(account_info_iter)?;
+    let lock_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let hook_accounts = account_info_iter.as_slice();
+
+    if stake_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut stake: Stake = bincode::deserialize(&stake_account.data.borrow())
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    if mint_account.key != &stake.mint || holder_account.key != &stake.holder {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let (vault_address, _) = find_stake_vault_address(program_id, stake_account.key);
+    if vault_account.key != &vault_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if owner_account.key != &stake.owner {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if stake.amount == 0 {
+        msg!("Stake is already withdrawn");
+        return Err(TokenError::StakeWithdrawn.into());
+    }
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+    if fee_collector_account.key != &fee_config.fee_collector {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let penalty = stake.unstake_penalty(
+        fee_config.early_unstake_penalty_basis_points,
+        Clock::get()?.unix_timestamp,
+    )?;
+    let returned_amount = stake
+        .amount
+        .checked_sub(penalty)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    for (destination_account, amount) in [
+        (fee_collector_account, penalty),
+        (holder_account, returned_amount),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        transfer_from_locked_account(
+            program_id,
+            mint_account,
+            vault_account,
+            destination_account,
+            lock_authority,
+            token_program,
+            hook_accounts,
+            amount,
+        )?;
+    }
+    msg!("Unstaked {} tokens, {} early unstake penalty", returned_amount, penalty);
+
+    stake.amount = 0;
+    store_stake(stake_account, &stake)?;
+
+    record_locked_balance(
+        program_id,
+        mint_account,
+        lock_authority,
+        rewards_program,
+        rewards_pool_account,
+        stake_account,
+        holder_account,
+        0,
+        stake.weight_basis_points,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_weight_basis_points() {
+        assert_eq!(lock_weight_basis_points(0), 10_000);
+        assert_eq!(lock_weight_basis_points(89 * SECONDS_PER_DAY), 10_000);
+        assert_eq!(lock_weight_basis_points(90 * SECONDS_PER_DAY), 15_000);
+        assert_eq!(lock_weight_basis_points(364 * SECONDS_PER_DAY), 15_000);
+        assert_eq!(lock_weight_basis_points(365 * SECONDS_PER_DAY), 20_000);
+        assert_eq!(lock_weight_basis_points(u64::MAX), 20_000);
+    }
+
+    #[test]
+    fn test_unstake_penalty() {
+        let stake = Stake {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            holder: Pubkey::new_unique(),
+            create_key: Pubkey::new_unique(),
+            amount: 1_000,
+            lock_end_timestamp: 5_000,
+            weight_basis_points: 15_000,
+            bump_seed: 255,
+        };
+
+        // Free once the lock has ended
+        assert_eq!(stake.unstake_penalty(None, 5_000), Ok(0));
+        assert_eq!(stake.unstake_penalty(Some(1_000), 6_000), Ok(0));
+
+        // Blocked or penalized before
+        assert_eq!(
+            stake.unstake_penalty(None, 4_999),
+            Err(TokenError::StakeLocked.into())
+        );
+        assert_eq!(stake.unstake_penalty(Some(1_000), 4_999), Ok(100));
+    }
+}
@@ -0,0 +1,237 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    program::invoke_signed,
+    system_instruction,
+    sysvar::{clock::Clock, Sysvar},
+};
+use spl_tlv_account_resolution::{account::ExtraAccountMeta, state::ExtraAccountMetaList};
+use spl_token_2022::{
+    extension::{
+        transfer_fee, transfer_hook::TransferHookAccount, BaseStateWithExtensions,
+        StateWithExtensions,
+    },
+    state::{Account, Mint},
+};
+use spl_transfer_hook_interface::{
+    collect_extra_account_metas_signer_seeds,
+    get_extra_account_metas_address_and_bump_seed,
+    instruction::{ExecuteInstruction, TransferHookInstruction},
+};
+
+use crate::{
+    check_rewards_accounts, emit_transfer_event, error::TokenError, get_mint_authority,
+    load_fee_config, multisig, sync_holder_balance, TransferDirection,
+};
+
+// Declare the program's entrypoint when building the transfer hook program
+#[cfg(feature = "transfer-hook-program")]
+solana_program::entrypoint!(process_instruction);
+
+// Program entrypoint implementation
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = TransferHookInstruction::unpack(instruction_data)?;
+
+    match instruction {
+        TransferHookInstruction::Execute { amount } => {
+            msg!("Instruction: Execute");
+            process_execute(program_id, accounts, amount)
+        }
+        TransferHookInstruction::InitializeExtraAccountMetaList {
+            extra_account_metas,
+        } => {
+            msg!("Instruction: InitializeExtraAccountMetaList");
+            process_initialize_extra_account_meta_list(program_id, accounts, &extra_account_metas)
+        }
+    }
+}
+
+// Runs on every transfer of the mint, including ones that bypass the token
+// program. Extra accounts, in order: the mint's fee config PDA, the rewards
+// program and the rewards pool.
+fn process_execute(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let destination_account = next_account_info(account_info_iter)?;
+    let owner_account = next_account_info(account_info_iter)?;
+    let extra_account_metas_account = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+
+    // Only run in the middle of a Token-2022 transfer, never when invoked directly
+    check_transferring(source_account)?;
+    check_transferring(destination_account)?;
+
+    // Verify the extra accounts match the ones stored for the mint
+    let (extra_account_metas_address, _) =
+        get_extra_account_metas_address_and_bump_seed(mint_account.key, program_id);
+    if extra_account_metas_account.key != &extra_account_metas_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    ExtraAccountMetaList::check_account_infos::<ExecuteInstruction>(
+        accounts,
+        &TransferHookInstruction::Execute { amount }.pack(),
+        program_id,
+        &extra_account_metas_account.data.borrow(),
+    )?;
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    check_rewards_accounts(&fee_config, rewards_program, rewards_pool_account)?;
+
+    // Buys and sells are recognised from the registered AMM pool accounts
+    let direction = fee_config.classify_transfer(source_account.key, destination_account.key);
+    msg!("Transfer direction: {:?}", direction);
+
+    // Transfers that bypass the token program are held to the blocklist, the
+    // launch controls and the anti-whale limits too
+    let source_owner = StateWithExtensions::<Account>::unpack(&source_account.data.borrow())?
+        .base
+        .owner;
+    let destination_owner =
+        StateWithExtensions::<Account>::unpack(&destination_account.data.borrow())?
+            .base
+            .owner;
+    fee_config.check_not_blocked(&[
+        source_account.key,
+        destination_account.key,
+        owner_account.key,
+        &source_owner,
+        &destination_owner,
+    ])?;
+    if !fee_config.is_fee_exempt(source_account.key, destination_account.key) {
+        fee_config.check_trading_enabled()?;
+        fee_config.check_transaction_amount(direction, amount)?;
+        let destination_balance =
+            StateWithExtensions::<Account>::unpack(&destination_account.data.borrow())?
+                .base
+                .amount;
+        fee_config.check_wallet_balance(direction, destination_balance)?;
+
+        // The sell limits need the seller's sell record, which only the token
+        // program's Transfer takes, so sells have to go through it
+        if direction == TransferDirection::Sell
+            && fee_config.has_sell_limits()
+            && !fee_config.in_transfer
+        {
+            return Err(TokenError::UncheckedSell.into());
+        }
+    }
+
+    // Update the balances of both sides in the rewards pool. The rewards program
+    // skips holders it has no room for instead of failing, so this never blocks
+    // a transfer.
+    sync_holder_balance(rewards_program, rewards_pool_account, source_account)?;
+    sync_holder_balance(rewards_program, rewards_pool_account, destination_account)?;
+
+    // The token program's Transfer logs its own event, a direct transfer is only
+    // charged the fee the extension withholds
+    if !fee_config.in_transfer {
+        let fee_amount = StateWithExtensions::<Mint>::unpack(&mint_account.data.borrow())?
+            .get_extension::<transfer_fee::TransferFeeConfig>()?
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        emit_transfer_event(
+            mint_account.key,
+            source_account.key,
+            destination_account.key,
+            direction,
+            amount,
+            amount - fee_amount,
+            0,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Expects the extra account metas PDA, the mint, the mint authority, the system
+// program and the mint's fee config PDA. Any remaining accounts are the signers
+// of a multisig mint authority.
+fn process_initialize_extra_account_meta_list(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    extra_account_metas: &[ExtraAccountMeta],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let extra_account_metas_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let signer_accounts = account_info_iter.as_slice();
+
+    // The fee config PDA is the withdraw authority of the mint's fee extension
+    let mint_data = mint_account.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+    let withdraw_authority: Option<Pubkey> = mint
+        .get_extension::<transfer_fee::TransferFeeConfig>()?
+        .withdraw_withheld_authority
+        .into();
+    if withdraw_authority != Some(*config_account.key) {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    drop(mint_data);
+
+    // Only the mint authority may set the extra accounts. It is checked against the
+    // token program, which owns the fee config and any multisig mint authority.
+    let token_program_id = config_account.owner;
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    let mint_authority = get_mint_authority(token_program_id, &fee_config, mint_account)?
+        .ok_or(ProgramError::InvalidAccountData)?;
+    multisig::check_authority(
+        token_program_id,
+        &mint_authority,
+        authority_account,
+        signer_accounts,
+    )?;
+
+    let (extra_account_metas_address, bump_seed) =
+        get_extra_account_metas_address_and_bump_seed(mint_account.key, program_id);
+    if extra_account_metas_account.key != &extra_account_metas_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // The account is expected to be funded for rent already
+    let bump_seed = [bump_seed];
+    let signer_seeds = collect_extra_account_metas_signer_seeds(mint_account.key, &bump_seed);
+    let account_size = ExtraAccountMetaList::size_of(extra_account_metas.len())?;
+
+    invoke_signed(
+        &system_instruction::allocate(extra_account_metas_account.key, account_size as u64),
+        &[extra_account_metas_account.clone(), system_program.clone()],
+        &[&signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(extra_account_metas_account.key, program_id),
+        &[extra_account_metas_account.clone(), system_program.clone()],
+        &[&signer_seeds],
+    )?;
+
+    let mut data = extra_account_metas_account.data.borrow_mut();
+    ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, extra_account_metas)?;
+
+    Ok(())
+}
+
+fn check_transferring(token_account: &AccountInfo) -> ProgramResult {
+    let account_data = token_account.data.borrow();
+    let account = StateWithExtensions::<Account>::unpack(&account_data)?;
+    let extension = account.get_extension::<TransferHookAccount>()?;
+    if !bool::from(extension.transferring) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
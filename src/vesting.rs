@@ -0,0 +1,485 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    system_program,
+    clock::Clock,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+use serde::{Deserialize, Serialize};
+use spl_token_2022::{extension::StateWithExtensions, state::Account};
+
+use crate::{
+    check_rewards_accounts, create_locked_token_account, error::TokenError,
+    find_lock_authority_address, load_fee_config, next_fee_config_account, record_locked_balance,
+    token_account_amount, transfer_from_locked_account, transfer_tokens, TransferFeeConfig,
+    FULL_WEIGHT_BASIS_POINTS,
+};
+
+// Seed of vesting PDAs, together with the mint and the key the vesting was
+// created with
+pub const VESTING_SEED: &[u8] = b"vesting";
+
+// Seed of the escrow token account of a vesting, together with the vesting
+// address. Escrows are held by the lock authority PDA of the mint.
+pub const VESTING_ESCROW_SEED: &[u8] = b"vesting_escrow";
+
+// Size of a bincode serialized Vesting
+pub const VESTING_ACCOUNT_LEN: usize = 32 * 4 + 8 * 5 + 1 + 1;
+
+// Tokens released linearly to a beneficiary, none of them before the cliff
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vesting {
+    mint: Pubkey,
+    beneficiary: Pubkey, // Token account the vested tokens are released to
+    authority: Pubkey, // Funded the vesting, may revoke it if revocable
+    create_key: Pubkey,
+    start_timestamp: i64,
+    cliff_timestamp: i64,
+    duration_seconds: u64, // Tokens vest linearly from the start over this
+    total_amount: u64, // Tokens put in the escrow
+    released_amount: u64,
+    revocable: bool,
+    bump_seed: u8,
+}
+
+impl Vesting {
+    // Tokens vested by `timestamp`, whether released or not
+    fn vested_amount(&self, timestamp: i64) -> u64 {
+        if timestamp < self.cliff_timestamp {
+            return 0;
+        }
+        let elapsed = timestamp.saturating_sub(self.start_timestamp).max(0) as u64;
+        if elapsed >= self.duration_seconds {
+            return self.total_amount;
+        }
+        ((self.total_amount as u128 * elapsed as u128) / self.duration_seconds as u128) as u64
+    }
+
+    // Tokens vested by `timestamp` that are yet to be released
+    fn releasable_amount(&self, timestamp: i64) -> u64 {
+        self.vested_amount(timestamp).saturating_sub(self.released_amount)
+    }
+
+    // Tokens still in the escrow
+    fn locked_amount(&self) -> u64 {
+        self.total_amount.saturating_sub(self.released_amount)
+    }
+}
+
+pub fn find_vesting_address(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    create_key: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VESTING_SEED, mint.as_ref(), create_key.as_ref()], program_id)
+}
+
+pub fn find_vesting_escrow_address(program_id: &Pubkey, vesting: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VESTING_ESCROW_SEED, vesting.as_ref()], program_id)
+}
+
+fn load_vesting(
+    program_id: &Pubkey,
+    vesting_account: &AccountInfo,
+) -> Result<Vesting, ProgramError> {
+    if vesting_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    bincode::deserialize(&vesting_account.data.borrow())
+        .map_err(|_| ProgramError::InvalidAccountData)
+}
+
+fn store_vesting(vesting_account: &AccountInfo, vesting: &Vesting) -> ProgramResult {
+    bincode::serialize_into(&mut &mut vesting_account.data.borrow_mut()[..], vesting)
+        .map_err(|_| ProgramError::AccountDataTooSmall)
+}
+
+// Report the tokens left in the escrow as the beneficiary's locked position in
+// the rewards pool, or nothing if vesting tokens don't earn rewards
+#[allow(clippy::too_many_arguments)]
+fn record_vesting_position<'a>(
+    program_id: &Pubkey,
+    fee_config: &TransferFeeConfig,
+    vesting: &Vesting,
+    mint_account: &AccountInfo<'a>,
+    vesting_account: &AccountInfo<'a>,
+    beneficiary_account: &AccountInfo<'a>,
+    lock_authority: &AccountInfo<'a>,
+    rewards_program: &AccountInfo<'a>,
+    rewards_pool_account: &AccountInfo<'a>,
+) -> ProgramResult {
+    check_rewards_accounts(fee_config, rewards_program, rewards_pool_account)?;
+    let balance = if fee_config.vesting_counts_for_rewards {
+        vesting.locked_amount()
+    } else {
+        0
+    };
+    record_locked_balance(
+        program_id,
+        mint_account,
+        lock_authority,
+        rewards_program,
+        rewards_pool_account,
+        vesting_account,
+        beneficiary_account,
+        balance,
+        FULL_WEIGHT_BASIS_POINTS,
+    )
+}
+
+// Lock `amount` in a new escrow vesting to the beneficiary token account.
+// Accounts: the vesting PDA, the escrow PDA, the key the vesting is derived from,
+// the mint, the fee config PDA, the source token account, its owner (signer, may
+// revoke if revocable), the beneficiary token account, the payer, the system
+// program, the token program, the lock authority PDA, the rewards program, the
+// rewards pool and the transfer hook accounts.
+pub fn process_create_vesting(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    start_timestamp: i64,
+    cliff_seconds: u64,
+    duration_seconds: u64,
+    amount: u64,
+    revocable: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vesting_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let create_key = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let source_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let beneficiary_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let lock_authority = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let hook_accounts = account_info_iter.as_slice();
+
+    // Escrows are Token-2022 accounts, so only native mints can vest
+    if mint_account.owner != &spl_token_2022::id() || token_program.key != &spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if system_program_account.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if amount == 0 || cliff_seconds > duration_seconds {
+        msg!(
+            "Invalid vesting: {} tokens, {}s cliff, {}s duration",
+            amount,
+            cliff_seconds,
+            duration_seconds
+        );
+        return Err(TokenError::InvalidVestingSchedule.into());
+    }
+    let cliff_timestamp = i64::try_from(cliff_seconds)
+        .ok()
+        .and_then(|cliff_seconds| start_timestamp.checked_add(cliff_seconds))
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    let fee_config = load_fee_config(mint_account, config_account)?;
+    let beneficiary =
+        StateWithExtensions::<Account>::unpack(&beneficiary_account.data.borrow())?.base;
+    if beneficiary.mint != *mint_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (vesting_address, bump_seed) =
+        find_vesting_address(program_id, mint_account.key, create_key.key);
+    if vesting_account.key != &vesting_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let (escrow_address, escrow_bump_seed) =
+        find_vesting_escrow_address(program_id, &vesting_address);
+    if escrow_account.key != &escrow_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let (lock_authority_address, _) = find_lock_authority_address(program_id, mint_account.key);
+    if lock_authority.key != &lock_authority_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            vesting_account.key,
+            Rent::get()?.minimum_balance(VESTING_ACCOUNT_LEN),
+            VESTING_ACCOUNT_LEN as u64,
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            vesting_account.clone(),
+            system_program_account.clone(),
+        ],
+        &[&[
+            VESTING_SEED,
+            mint_account.key.as_ref(),
+            create_key.key.as_ref(),
+            &[bump_seed],
+        ]],
+    )?;
+
+    create_locked_token_account(
+        payer,
+        escrow_account,
+        mint_account,
+        lock_authority,
+        system_program_account,
+        token_program,
+        &[VESTING_ESCROW_SEED, vesting_address.as_ref(), &[escrow_bump_seed]],
+    )?;
+
+    // Fee mints withhold part of the transfer, so only what reached the escrow vests
+    transfer_tokens(
+        source_account,
+        mint_account,
+        escrow_account,
+        authority_account,
+        token_program,
+        hook_accounts,
+        amount,
+        &[],
+    )?;
+    let total_amount = token_account_amount(escrow_account)?;
+
+    let vesting = Vesting {
+        mint: *mint_account.key,
+        beneficiary: *beneficiary_account.key,
+        authority: *authority_account.key,
+        create_key: *create_key.key,
+        start_timestamp,
+        cliff_timestamp,
+        duration_seconds,
+        total_amount,
+        released_amount: 0,
+        revocable,
+        bump_seed,
+    };
+    msg!("Locked {} tokens for {}", total_amount, beneficiary_account.key);
+    store_vesting(vesting_account, &vesting)?;
+
+    record_vesting_position(
+        program_id,
+        &fee_config,
+        &vesting,
+        mint_account,
+        vesting_account,
+        beneficiary_account,
+        lock_authority,
+        rewards_program,
+        rewards_pool_account,
+    )
+}
+
+// Release whatever has vested to the beneficiary. Anyone may release, the
+// tokens only ever go to the beneficiary token account. Accounts: the vesting,
+// the escrow, the mint, the fee config PDA, the beneficiary token account, the
+// lock authority PDA, the token program, the rewards program, the rewards pool
+// and the transfer hook accounts.
+pub fn process_release_vested(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vesting_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let beneficiary_account = next_account_info(account_info_iter)?;
+    let lock_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let hook_accounts = account_info_iter.as_slice();
+
+    let mut vesting = load_vesting(program_id, vesting_account)?;
+    check_vesting_accounts(program_id, &vesting, vesting_account, escrow_account, mint_account)?;
+    if beneficiary_account.key != &vesting.beneficiary {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let fee_config = load_fee_config(mint_account, config_account)?;
+
+    let releasable_amount = vesting.releasable_amount(Clock::get()?.unix_timestamp);
+    if releasable_amount == 0 {
+        msg!("Nothing to release yet");
+        return Err(TokenError::NothingToRelease.into());
+    }
+    transfer_from_locked_account(
+        program_id,
+        mint_account,
+        escrow_account,
+        beneficiary_account,
+        lock_authority,
+        token_program,
+        hook_accounts,
+        releasable_amount,
+    )?;
+    vesting.released_amount = vesting
+        .released_amount
+        .checked_add(releasable_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    msg!("Released {} of {} tokens", vesting.released_amount, vesting.total_amount);
+    store_vesting(vesting_account, &vesting)?;
+
+    record_vesting_position(
+        program_id,
+        &fee_config,
+        &vesting,
+        mint_account,
+        vesting_account,
+        beneficiary_account,
+        lock_authority,
+        rewards_program,
+        rewards_pool_account,
+    )
+}
+
+// Stop a revocable vesting. What has vested goes to the beneficiary, the rest
+// back to the authority's refund token account. Accounts: the vesting, the
+// escrow, the mint, the fee config PDA, the beneficiary token account, the
+// vesting authority (signer), the refund token account, the lock authority PDA,
+// the token program, the rewards program, the rewards pool and the transfer hook
+// accounts.
+pub fn process_revoke_vesting(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let vesting_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let mint_account = next_account_info(account_info_iter)?;
+    let config_account = next_fee_config_account(program_id, mint_account, account_info_iter)?;
+    let beneficiary_account = next_account_info(account_info_iter)?;
+    let authority_account = next_account_info(account_info_iter)?;
+    let refund_account = next_account_info(account_info_iter)?;
+    let lock_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let rewards_program = next_account_info(account_info_iter)?;
+    let rewards_pool_account = next_account_info(account_info_iter)?;
+    let hook_accounts = account_info_iter.as_slice();
+
+    let mut vesting = load_vesting(program_id, vesting_account)?;
+    check_vesting_accounts(program_id, &vesting, vesting_account, escrow_account, mint_account)?;
+    if beneficiary_account.key != &vesting.beneficiary {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if authority_account.key != &vesting.authority {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !vesting.revocable {
+        msg!("Vesting is not revocable");
+        return Err(TokenError::VestingNotRevocable.into());
+    }
+    let fee_config = load_fee_config(mint_account, config_account)?;
+
+    let timestamp = Clock::get()?.unix_timestamp;
+    let releasable_amount = vesting.releasable_amount(timestamp);
+    let unvested_amount = vesting.total_amount.saturating_sub(vesting.vested_amount(timestamp));
+    for (destination_account, amount) in [
+        (beneficiary_account, releasable_amount),
+        (refund_account, unvested_amount),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        transfer_from_locked_account(
+            program_id,
+            mint_account,
+            escrow_account,
+            destination_account,
+            lock_authority,
+            token_program,
+            hook_accounts,
+            amount,
+        )?;
+    }
+
+    // Nothing is left to vest
+    vesting.released_amount = vesting
+        .released_amount
+        .checked_add(releasable_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    vesting.total_amount = vesting.released_amount;
+    vesting.revocable = false;
+    msg!("Revoked vesting, returned {} tokens", unvested_amount);
+    store_vesting(vesting_account, &vesting)?;
+
+    record_vesting_position(
+        program_id,
+        &fee_config,
+        &vesting,
+        mint_account,
+        vesting_account,
+        beneficiary_account,
+        lock_authority,
+        rewards_program,
+        rewards_pool_account,
+    )
+}
+
+fn check_vesting_accounts(
+    program_id: &Pubkey,
+    vesting: &Vesting,
+    vesting_account: &AccountInfo,
+    escrow_account: &AccountInfo,
+    mint_account: &AccountInfo,
+) -> ProgramResult {
+    if mint_account.key != &vesting.mint {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let (escrow_address, _) = find_vesting_escrow_address(program_id, vesting_account.key);
+    if escrow_account.key != &escrow_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vested_amount() {
+        let mut vesting = Vesting {
+            mint: Pubkey::new_unique(),
+            beneficiary: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            create_key: Pubkey::new_unique(),
+            start_timestamp: 1_000,
+            cliff_timestamp: 1_100,
+            duration_seconds: 400,
+            total_amount: 1_000,
+            released_amount: 0,
+            revocable: true,
+            bump_seed: 255,
+        };
+
+        // Nothing before the cliff, then linear from the start
+        assert_eq!(vesting.vested_amount(900), 0);
+        assert_eq!(vesting.vested_amount(1_099), 0);
+        assert_eq!(vesting.vested_amount(1_100), 250);
+        assert_eq!(vesting.vested_amount(1_300), 750);
+        assert_eq!(vesting.vested_amount(1_400), 1_000);
+        assert_eq!(vesting.vested_amount(i64::MAX), 1_000);
+
+        vesting.released_amount = 250;
+        assert_eq!(vesting.releasable_amount(1_300), 500);
+        assert_eq!(vesting.locked_amount(), 750);
+
+        // Without a duration everything vests at the cliff
+        vesting.duration_seconds = 0;
+        vesting.cliff_timestamp = vesting.start_timestamp;
+        assert_eq!(vesting.vested_amount(999), 0);
+        assert_eq!(vesting.vested_amount(1_000), 1_000);
+    }
+}